@@ -0,0 +1,556 @@
+//! The alarms set by the user, the scheduling of the next due one into the RTC and the ringing
+//! once it went off.
+//!
+//! The RTC has a single alarm that matches on the weekday, hour and minute, so the `Scheduler`
+//! keeps it programmed to whichever of the `Alarm`s is due next and moves it on after it fired.
+//! The `Ringer` then rings until the alarm is dismissed or times out, with snoozes in between.
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::POLL_FREQ,
+    clock::{AlarmState, ClockState, Rtc},
+    settings::AlarmTone,
+    time::{Duration, Timestamp},
+};
+
+/// Number of alarms kept in the `Settings`.
+pub const ALARM_COUNT: usize = 4;
+
+/// How long an alarm rings before it stops by itself.
+pub const RING_TIMEOUT: Duration = Duration::from_minutes(10);
+
+/// Volume of the buzzer at full loudness, in percent.
+pub const MAX_VOLUME: u8 = 100;
+/// Volume in percent a ringing alarm starts at, quieter alarms starting at their own volume.
+pub const CRESCENDO_START: u8 = 10;
+/// How long a ringing alarm takes to get from `CRESCENDO_START` to its volume.
+pub const CRESCENDO_DURATION: Duration = Duration::from_minutes(1);
+
+/// A set of days of the week, bit 0 being monday like `ClockState::weekday` 1.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Weekdays(u8);
+
+impl Weekdays {
+    pub const NONE: Weekdays = Weekdays(0);
+    pub const WORKDAYS: Weekdays = Weekdays(0b001_1111);
+    pub const WEEKEND: Weekdays = Weekdays(0b110_0000);
+    pub const EVERY_DAY: Weekdays = Weekdays(0b111_1111);
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Weekdays(bits & Self::EVERY_DAY.0)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns `true` if the weekday, from 1 for monday to 7 for sunday, is in the set.
+    pub fn contains(self, weekday: u8) -> bool {
+        (1..=7).contains(&weekday) && self.0 & 1 << (weekday - 1) != 0
+    }
+
+    pub fn toggle(&mut self, weekday: u8) {
+        if (1..=7).contains(&weekday) {
+            self.0 ^= 1 << (weekday - 1);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Alarm {
+    pub hour: u8,
+    pub minute: u8,
+    /// Days of the week the alarm goes off on.
+    pub days: Weekdays,
+    pub enabled: bool,
+    /// Minutes before the set time in which the alarm goes off early when the movement suggests
+    /// light sleep, see `wake::SmartWake`. Zero to only go off at the set time.
+    pub window: u8,
+    /// Tone played while the alarm rings.
+    pub tone: AlarmTone,
+    /// Volume in percent the alarm rings at once the crescendo is over, see `Ringer::volume`.
+    pub volume: u8,
+}
+
+impl Default for Alarm {
+    fn default() -> Self {
+        Alarm {
+            hour: 7,
+            minute: 0,
+            days: Weekdays::EVERY_DAY,
+            enabled: false,
+            window: 0,
+            tone: AlarmTone::default(),
+            volume: MAX_VOLUME,
+        }
+    }
+}
+
+impl Alarm {
+    pub fn window(&self) -> Duration {
+        Duration::from_minutes(self.window as i64)
+    }
+}
+
+/// The alarm that goes off next after `now`, and when. An alarm of the current minute counts as
+/// gone off from its first second on.
+pub fn next_alarm(alarms: &[Alarm], now: &ClockState) -> Option<(usize, Timestamp)> {
+    let now = Timestamp::from(now);
+    let today = now.midnight();
+
+    // a week and a day ahead to find the alarm of today's weekday that went off already
+    (0..=7)
+        .flat_map(|day| {
+            let midnight = today + Duration::from_days(day);
+            let weekday = ClockState::from(midnight).weekday;
+            alarms.iter().enumerate().filter_map(move |(i, a)| {
+                let at = midnight + Duration::from_hours(a.hour as i64);
+                let at = at + Duration::from_minutes(a.minute as i64);
+                (a.enabled && a.days.contains(weekday) && at > now).then_some((i, at))
+            })
+        })
+        .min_by_key(|&(_, at)| at)
+}
+
+/// The alarm that is due in the minute of `now`, which is the one that fired if the RTC alarm
+/// just went off.
+pub fn due_alarm(alarms: &[Alarm], now: &ClockState) -> Option<usize> {
+    alarms.iter().position(|a| {
+        a.enabled && a.days.contains(now.weekday) && (a.hour, a.minute) == (now.hour, now.minute)
+    })
+}
+
+/// Keeps the alarm of the RTC programmed to the next due `Alarm`.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    /// The alarm the RTC was last programmed with, `None` before it was programmed at all.
+    programmed: Option<Option<(usize, Timestamp)>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The alarm the RTC is programmed with, and when it goes off.
+    pub fn next(&self) -> Option<(usize, Timestamp)> {
+        self.programmed.flatten()
+    }
+
+    /// Programs the RTC with the next due alarm if it changed, either because the alarms or the
+    /// clock were edited or because the programmed alarm went off.
+    pub fn update<R: Rtc>(&mut self, alarms: &[Alarm], rtc: &mut R) {
+        let next = next_alarm(alarms, &rtc.get_state());
+        if self.programmed == Some(next) {
+            return;
+        }
+
+        rtc.set_alarm(match next {
+            Some((_, at)) => {
+                let at = ClockState::from(at);
+                AlarmState {
+                    hour: at.hour,
+                    minute: at.minute,
+                    weekday: Some(at.weekday),
+                    enabled: true,
+                }
+            }
+            None => AlarmState::default(),
+        });
+        self.programmed = Some(next);
+    }
+
+    /// Returns the alarm that went off, if the RTC alarm was for one of `alarms`, and programs the
+    /// RTC with the one after it.
+    pub fn fired<R: Rtc>(&mut self, alarms: &[Alarm], rtc: &mut R) -> Option<usize> {
+        let fired = due_alarm(alarms, &rtc.get_state());
+        self.update(alarms, rtc);
+        fired
+    }
+}
+
+/// A transition of the `Ringer`, as it is logged.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RingEvent {
+    /// Started ringing for the alarm with the index, or for an RTC alarm that was not one of the
+    /// `Alarm`s. Also when a snooze is over.
+    Ring(Option<u8>),
+    Snooze,
+    Dismiss,
+    Timeout,
+    /// Started ringing for the alarm with the index and a wake window, for the reason.
+    Wake(u8, WakeReason),
+}
+
+/// Why an alarm with a wake window went off.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeReason {
+    /// The movement in the window suggested light sleep.
+    LightSleep,
+    /// The set time was reached without any sign of light sleep.
+    SetTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RingState {
+    Idle,
+    Ringing { alarm: Option<u8>, ticks: u32 },
+    Snoozed { alarm: Option<u8>, ticks_left: u32 },
+}
+
+/// Rings for an alarm that went off until it is dismissed, snoozing it in between. Stops by itself
+/// after `RING_TIMEOUT`. Driven by the ticks of `POLL_FREQ`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ringer {
+    state: RingState,
+}
+
+impl Default for Ringer {
+    fn default() -> Self {
+        Ringer {
+            state: RingState::Idle,
+        }
+    }
+}
+
+impl Ringer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_ringing(&self) -> bool {
+        matches!(self.state, RingState::Ringing { .. })
+    }
+
+    pub fn is_snoozed(&self) -> bool {
+        matches!(self.state, RingState::Snoozed { .. })
+    }
+
+    /// The alarm that is ringing or snoozed, if it is one of the `Alarm`s.
+    pub fn alarm(&self) -> Option<usize> {
+        match self.state {
+            RingState::Idle => None,
+            RingState::Ringing { alarm, .. } | RingState::Snoozed { alarm, .. } => {
+                alarm.map(usize::from)
+            }
+        }
+    }
+
+    /// Volume to ring at for an alarm of `volume`, rising from `CRESCENDO_START` to it over the
+    /// first `CRESCENDO_DURATION` of ringing, which starts over after a snooze. Zero while not
+    /// ringing.
+    pub fn volume(&self, volume: u8) -> u8 {
+        let ticks = match self.state {
+            RingState::Ringing { ticks, .. } => ticks as i64,
+            _ => return 0,
+        };
+        let duration = CRESCENDO_DURATION.seconds() * POLL_FREQ as i64;
+        let start = CRESCENDO_START.min(volume) as i64;
+        let rise = (volume as i64 - start) * ticks.min(duration) / duration;
+        (start + rise) as u8
+    }
+
+    /// Starts ringing for the alarm that went off, replacing any alarm ringing or snoozed before.
+    pub fn ring(&mut self, alarm: Option<usize>) -> RingEvent {
+        let alarm = alarm.map(|i| i as u8);
+        self.state = RingState::Ringing { alarm, ticks: 0 };
+        RingEvent::Ring(alarm)
+    }
+
+    /// Starts ringing for the alarm with a wake window, like `ring`.
+    pub fn wake(&mut self, alarm: usize, reason: WakeReason) -> RingEvent {
+        self.ring(Some(alarm));
+        RingEvent::Wake(alarm as u8, reason)
+    }
+
+    /// Stops ringing for the given number of minutes.
+    pub fn snooze(&mut self, minutes: u8) -> Option<RingEvent> {
+        match self.state {
+            RingState::Ringing { alarm, .. } => {
+                self.state = RingState::Snoozed {
+                    alarm,
+                    ticks_left: minutes.max(1) as u32 * 60 * POLL_FREQ,
+                };
+                Some(RingEvent::Snooze)
+            }
+            _ => None,
+        }
+    }
+
+    /// Stops ringing, or cancels the snooze.
+    pub fn dismiss(&mut self) -> Option<RingEvent> {
+        match self.state {
+            RingState::Idle => None,
+            _ => {
+                self.state = RingState::Idle;
+                Some(RingEvent::Dismiss)
+            }
+        }
+    }
+
+    /// Rings again once the snooze is over and stops ringing after `RING_TIMEOUT`.
+    pub fn tick(&mut self) -> Option<RingEvent> {
+        match self.state {
+            RingState::Idle => None,
+            RingState::Ringing { alarm, ticks } => {
+                let ticks = ticks + 1;
+                if ticks as i64 >= RING_TIMEOUT.seconds() * POLL_FREQ as i64 {
+                    self.state = RingState::Idle;
+                    Some(RingEvent::Timeout)
+                } else {
+                    self.state = RingState::Ringing { alarm, ticks };
+                    None
+                }
+            }
+            RingState::Snoozed { alarm, ticks_left } if ticks_left <= 1 => {
+                Some(self.ring(alarm.map(usize::from)))
+            }
+            RingState::Snoozed { alarm, ticks_left } => {
+                self.state = RingState::Snoozed {
+                    alarm,
+                    ticks_left: ticks_left - 1,
+                };
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeRtc {
+        state: ClockState,
+        alarm: AlarmState,
+        writes: usize,
+    }
+
+    impl Rtc for FakeRtc {
+        fn get_state(&self) -> ClockState {
+            self.state
+        }
+
+        fn set_state(&mut self, state: ClockState) {
+            self.state = state;
+        }
+
+        fn get_alarm(&self) -> AlarmState {
+            self.alarm
+        }
+
+        fn set_alarm(&mut self, alarm: AlarmState) {
+            self.alarm = alarm;
+            self.writes += 1;
+        }
+
+        fn alarm_reset(&mut self) {}
+    }
+
+    fn alarms() -> [Alarm; ALARM_COUNT] {
+        [
+            Alarm {
+                hour: 6,
+                minute: 30,
+                days: Weekdays::WORKDAYS,
+                enabled: true,
+                window: 0,
+                tone: AlarmTone::Beep,
+                volume: MAX_VOLUME,
+            },
+            Alarm {
+                hour: 9,
+                minute: 0,
+                days: Weekdays::WEEKEND,
+                enabled: true,
+                window: 0,
+                tone: AlarmTone::Beep,
+                volume: MAX_VOLUME,
+            },
+            Alarm {
+                hour: 5,
+                minute: 0,
+                enabled: false,
+                ..Alarm::default()
+            },
+            Alarm::default(),
+        ]
+    }
+
+    /// The clock at the given time on a day of the week of 2021-09-20, a monday.
+    fn at(weekday: u8, hour: u8, minute: u8, second: u8) -> ClockState {
+        let mut c = ClockState {
+            hour,
+            minute,
+            second,
+            ..ClockState::default()
+        };
+        c.set_date(19 + weekday, 9, 21);
+        c
+    }
+
+    #[test]
+    fn weekdays() {
+        assert!(Weekdays::WORKDAYS.contains(1));
+        assert!(Weekdays::WORKDAYS.contains(5));
+        assert!(!Weekdays::WORKDAYS.contains(6));
+        assert!(Weekdays::WEEKEND.contains(7));
+        assert!(!Weekdays::EVERY_DAY.contains(0));
+        assert!(!Weekdays::EVERY_DAY.contains(8));
+
+        let mut days = Weekdays::NONE;
+        days.toggle(3);
+        days.toggle(8);
+        assert_eq!(days.bits(), 0b100);
+        assert_eq!(Weekdays::from_bits(0xff), Weekdays::EVERY_DAY);
+    }
+
+    #[test]
+    fn finds_next_alarm_by_weekday() {
+        let alarms = alarms();
+        let next = |c: ClockState| next_alarm(&alarms, &c).map(|(i, t)| (i, ClockState::from(t)));
+
+        // monday night, the workday alarm of tuesday
+        let (i, c) = next(at(1, 22, 0, 0)).unwrap();
+        assert_eq!((i, c.weekday, c.hour, c.minute), (0, 2, 6, 30));
+
+        // friday after the alarm, the weekend alarm of saturday
+        let (i, c) = next(at(5, 6, 30, 0)).unwrap();
+        assert_eq!((i, c.weekday, c.day, c.hour), (1, 6, 25, 9));
+
+        // sunday after the alarm, monday of the next week
+        let (i, c) = next(at(7, 12, 0, 0)).unwrap();
+        assert_eq!((i, c.weekday, c.day, c.month), (0, 1, 27, 9));
+
+        // a single weekly alarm that just went off is due again a week later
+        let weekly = [Alarm {
+            days: Weekdays::from_bits(1),
+            enabled: true,
+            ..Alarm::default()
+        }];
+        let (_, t) = next_alarm(&weekly, &at(1, 7, 0, 0)).unwrap();
+        assert_eq!(t - Timestamp::from(at(1, 7, 0, 0)), Duration::from_days(7));
+
+        assert_eq!(next_alarm(&[Alarm::default()], &at(1, 0, 0, 0)), None);
+    }
+
+    #[test]
+    fn finds_due_alarm() {
+        let alarms = alarms();
+        assert_eq!(due_alarm(&alarms, &at(2, 6, 30, 0)), Some(0));
+        assert_eq!(due_alarm(&alarms, &at(2, 6, 30, 12)), Some(0));
+        assert_eq!(due_alarm(&alarms, &at(6, 6, 30, 0)), None);
+        assert_eq!(due_alarm(&alarms, &at(6, 9, 0, 0)), Some(1));
+        assert_eq!(due_alarm(&alarms, &at(2, 5, 0, 0)), None);
+    }
+
+    #[test]
+    fn ringer_snoozes_and_dismisses() {
+        let mut ringer = Ringer::new();
+        assert_eq!(ringer.tick(), None);
+        assert_eq!(ringer.snooze(5), None);
+        assert_eq!(ringer.dismiss(), None);
+
+        assert_eq!(ringer.ring(Some(1)), RingEvent::Ring(Some(1)));
+        assert!(ringer.is_ringing());
+        assert_eq!(ringer.alarm(), Some(1));
+
+        assert_eq!(ringer.snooze(1), Some(RingEvent::Snooze));
+        assert!(ringer.is_snoozed());
+        assert_eq!(ringer.snooze(1), None);
+        for _ in 1..60 * POLL_FREQ {
+            assert_eq!(ringer.tick(), None);
+        }
+        assert_eq!(ringer.tick(), Some(RingEvent::Ring(Some(1))));
+        assert!(ringer.is_ringing());
+
+        // the snooze can be cancelled as well
+        ringer.snooze(9);
+        assert_eq!(ringer.dismiss(), Some(RingEvent::Dismiss));
+        assert!(!ringer.is_ringing() && !ringer.is_snoozed());
+        assert_eq!(ringer.alarm(), None);
+    }
+
+    #[test]
+    fn ringer_times_out() {
+        let mut ringer = Ringer::new();
+        ringer.ring(None);
+        let ticks = RING_TIMEOUT.seconds() as u32 * POLL_FREQ;
+        for _ in 1..ticks {
+            assert_eq!(ringer.tick(), None);
+        }
+        assert_eq!(ringer.tick(), Some(RingEvent::Timeout));
+        assert!(!ringer.is_ringing());
+        assert_eq!(ringer.tick(), None);
+    }
+
+    #[test]
+    fn ringer_crescendo() {
+        let mut ringer = Ringer::new();
+        assert_eq!(ringer.volume(80), 0);
+
+        ringer.ring(Some(0));
+        assert_eq!(ringer.volume(80), CRESCENDO_START);
+        for _ in 0..30 * POLL_FREQ {
+            ringer.tick();
+        }
+        assert_eq!(ringer.volume(80), 45);
+        assert_eq!(ringer.volume(MAX_VOLUME), 55);
+        for _ in 0..60 * POLL_FREQ {
+            ringer.tick();
+        }
+        assert_eq!(ringer.volume(80), 80);
+        // a quiet alarm stays quiet
+        assert_eq!(ringer.volume(5), 5);
+
+        // and starts over after a snooze
+        ringer.snooze(1);
+        assert_eq!(ringer.volume(80), 0);
+        for _ in 0..60 * POLL_FREQ {
+            ringer.tick();
+        }
+        assert!(ringer.is_ringing());
+        assert_eq!(ringer.volume(80), CRESCENDO_START);
+    }
+
+    #[test]
+    fn scheduler_programs_next_alarm() {
+        let mut alarms = alarms();
+        let mut rtc = FakeRtc {
+            state: at(5, 6, 29, 59),
+            ..FakeRtc::default()
+        };
+        let mut scheduler = Scheduler::new();
+
+        scheduler.update(&alarms, &mut rtc);
+        let programmed = AlarmState {
+            hour: 6,
+            minute: 30,
+            weekday: Some(5),
+            enabled: true,
+        };
+        assert_eq!(rtc.alarm, programmed);
+
+        // nothing is written while the next alarm stays the same
+        scheduler.update(&alarms, &mut rtc);
+        assert_eq!(rtc.writes, 1);
+
+        // the alarm fires and the weekend alarm is programmed
+        rtc.state = at(5, 6, 30, 0);
+        assert_eq!(scheduler.fired(&alarms, &mut rtc), Some(0));
+        assert_eq!((rtc.alarm.hour, rtc.alarm.weekday), (9, Some(6)));
+        assert_eq!(scheduler.next().unwrap().0, 1);
+
+        // disabling all alarms disables the RTC alarm
+        alarms.iter_mut().for_each(|a| a.enabled = false);
+        scheduler.update(&alarms, &mut rtc);
+        assert!(!rtc.alarm.enabled);
+        assert_eq!(scheduler.next(), None);
+        assert_eq!(rtc.writes, 3);
+    }
+}
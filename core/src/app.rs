@@ -0,0 +1,624 @@
+//! The user interface logic of the main loop, shared by the firmware and the simulator.
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::{
+    alarm::{RingEvent, Ringer, Scheduler, WakeReason, MAX_VOLUME},
+    clock::{ClockState, Rtc},
+    display::Display,
+    event::InterruptEvent,
+    light::{self, Backlight},
+    mpu::Measurement,
+    panel::{CursorState, Panel},
+    settings::{AlarmTone, Settings},
+    storage::SettingsSource,
+    time::Timestamp,
+    wake::SmartWake,
+    Diagnostics, SharedState, Stats,
+};
+
+/// Frequency in Hz at which the inputs are polled and `InterruptEvent::Tick` is generated.
+pub const POLL_FREQ: u32 = 10;
+
+/// Seconds the button has to be held down to generate a `InterruptEvent::LongPress`.
+pub const LONG_PRESS_DURATION: u32 = 2;
+
+pub struct App<'a, D: Display> {
+    panel: &'a mut dyn Panel<D>,
+    state: SharedState,
+    /// The date and time last read from the clock.
+    synced: ClockState,
+    scheduler: Scheduler,
+    ringer: Ringer,
+    wake: SmartWake,
+    backlight: Backlight,
+    /// The last transition of the `ringer`, until it is taken by the main loop.
+    ring_event: Option<RingEvent>,
+    last_edit_state: bool,
+}
+
+impl<'a, D: Display> App<'a, D> {
+    pub fn new<R: Rtc>(panel: &'a mut dyn Panel<D>, rtc: &R, settings: Settings) -> Self {
+        let clock = rtc.get_state();
+        App {
+            panel,
+            state: SharedState {
+                clock,
+                settings,
+                measurement: None,
+                stats: Stats::default(),
+                diagnostics: Diagnostics::default(),
+            },
+            synced: clock,
+            scheduler: Scheduler::new(),
+            ringer: Ringer::new(),
+            wake: SmartWake::new(),
+            backlight: Backlight::new(),
+            ring_event: None,
+            last_edit_state: false,
+        }
+    }
+
+    pub fn state(&self) -> &SharedState {
+        &self.state
+    }
+
+    /// Gives access to the state for what the main loop keeps up to date, like the measurements.
+    pub fn state_mut(&mut self) -> &mut SharedState {
+        &mut self.state
+    }
+
+    /// The ringing or snoozed alarm, see `Ringer`.
+    pub fn ringer(&self) -> &Ringer {
+        &self.ringer
+    }
+
+    /// The tone to play for the ringing alarm, the default one if it is not one of the `Alarm`s.
+    pub fn alarm_tone(&self) -> AlarmTone {
+        self.ringer
+            .alarm()
+            .map(|i| self.state.settings.alarms[i].tone)
+            .unwrap_or_default()
+    }
+
+    /// The volume to play the tone at, following the crescendo of the `Ringer` up to the volume of
+    /// the ringing alarm.
+    pub fn alarm_volume(&self) -> u8 {
+        let volume = self
+            .ringer
+            .alarm()
+            .map_or(MAX_VOLUME, |i| self.state.settings.alarms[i].volume);
+        self.ringer.volume(volume)
+    }
+
+    /// Returns the transition of the `Ringer` caused by the last handled event, to be logged.
+    pub fn take_ring_event(&mut self) -> Option<RingEvent> {
+        self.ring_event.take()
+    }
+
+    /// Brightness the display backlight should have, see `light::brightness`. An alarm that
+    /// already went off early gets no more sunrise.
+    pub fn backlight(&self) -> u8 {
+        let now = Timestamp::from(&self.state.clock);
+        let alarm = self
+            .scheduler
+            .next()
+            .filter(|&(i, at)| !self.wake.is_woken(i, at))
+            .map(|(_, at)| at);
+        let ringing = self.ringer.is_ringing() || self.ringer.is_snoozed();
+        let idle = self.backlight.is_idle(&self.state.settings);
+        light::brightness(&self.state.settings, now, alarm, ringing, idle)
+    }
+
+    /// Records a new measurement of the MPU, which may wake up early for the next alarm. The time
+    /// is read from the clock, since the one of the state may be edited.
+    pub fn record_measurement<R: Rtc>(&mut self, m: &Measurement, rtc: &R) {
+        self.state.stats.record(m);
+        self.state.measurement = Some(m.clone());
+
+        let now = Timestamp::from(rtc.get_state());
+        let alarms = &self.state.settings.alarms;
+        if let Some(i) = self.wake.record(m, now, self.scheduler.next(), alarms) {
+            if !self.ringer.is_ringing() {
+                self.ring_event = Some(self.ringer.wake(i, WakeReason::LightSleep));
+            }
+        }
+    }
+
+    /// Handles one event.
+    pub fn handle_event<R: Rtc>(&mut self, evt: InterruptEvent, rtc: &mut R) {
+        use InterruptEvent::*;
+
+        match evt {
+            Tick => self.backlight.tick(),
+            // the first input to the dimmed backlight only wakes it up, unless an alarm rings
+            Encoder(_) | ShortPress | LongPress => {
+                if self.backlight.input(&self.state.settings) && !self.ringer.is_ringing() {
+                    return;
+                }
+            }
+            Alarm => (),
+        }
+
+        match evt {
+            Tick => {
+                self.state.stats.ticks = self.state.stats.ticks.wrapping_add(1);
+
+                // write the edited date and time to the clock when the panel stops editing, but
+                // only if they were edited, since the clock kept running in the meantime
+                if self.last_edit_state
+                    && !self.panel.is_editing()
+                    && self.state.clock != self.synced
+                {
+                    rtc.set_state(self.state.clock);
+                }
+
+                // fetch the current date and time from the clock if the panel is not editing
+                if !self.panel.is_editing() {
+                    self.state.clock = rtc.get_state();
+                    self.synced = self.state.clock;
+                }
+
+                // the alarms do not depend on the edited clock, so the next one is programmed
+                // even while editing
+                self.scheduler.update(&self.state.settings.alarms, rtc);
+
+                self.last_edit_state = self.panel.is_editing();
+
+                if let Some(e) = self.ringer.tick() {
+                    self.ring_event = Some(e);
+                }
+            }
+            // while ringing the button snoozes or dismisses the alarm and the encoder does nothing
+            Encoder(_) | ShortPress | LongPress if self.ringer.is_ringing() => {
+                self.ring_event = match evt {
+                    ShortPress => self.ringer.snooze(self.state.settings.snooze_duration),
+                    LongPress => self.ringer.dismiss(),
+                    _ => None,
+                };
+            }
+            // while snoozed a long press outside of editing cancels the snooze
+            LongPress if self.ringer.is_snoozed() && !self.panel.is_editing() => {
+                self.ring_event = self.ringer.dismiss();
+            }
+            Encoder(change) => {
+                let mut c = change;
+                while c > 0 {
+                    self.panel.next(&mut self.state);
+                    c -= 1;
+                }
+
+                while c < 0 {
+                    self.panel.previous(&mut self.state);
+                    c += 1;
+                }
+            }
+            LongPress => self.panel.leave(&mut self.state),
+            ShortPress => self.panel.enter(&mut self.state),
+            Alarm => {
+                rtc.alarm_reset();
+                let now = Timestamp::from(rtc.get_state());
+                let alarms = self.state.settings.alarms;
+                // an alarm with a wake window that already went off early does not ring again
+                self.ring_event = match self.scheduler.fired(&alarms, rtc) {
+                    Some(i) if self.wake.take_woken(i, now) => None,
+                    Some(i) if alarms[i].window > 0 => {
+                        Some(self.ringer.wake(i, WakeReason::SetTime))
+                    }
+                    Some(i) => Some(self.ringer.ring(Some(i))),
+                    // without stored settings the RTC may still hold the single alarm of older
+                    // firmware, otherwise it was an alarm that has been changed since
+                    None if !matches!(
+                        self.state.diagnostics.settings_source,
+                        SettingsSource::Stored { .. }
+                    ) =>
+                    {
+                        Some(self.ringer.ring(None))
+                    }
+                    None => None,
+                };
+            }
+        }
+    }
+
+    /// Draws the ringing alarm or the panel and returns where the cursor should be shown.
+    pub fn render(&mut self, disp: &mut D) -> Result<CursorState, D::Error> {
+        disp.clear()?;
+
+        if self.ringer.is_ringing() {
+            self.render_ringing(disp)?;
+            Ok(CursorState::Off)
+        } else {
+            self.panel.display(disp, &mut self.state)?;
+            Ok(self.panel.get_cursor_state(&self.state))
+        }
+    }
+
+    /// Shows the ringing alarm and the time, and what the button does.
+    fn render_ringing(&self, disp: &mut D) -> Result<(), D::Error> {
+        let mut title: String<20> = String::new();
+        let _ = match self.ringer.alarm() {
+            Some(i) => write!(title, "ALARM {}", i + 1),
+            None => write!(title, "ALARM"),
+        };
+        let c = &self.state.clock;
+        let mut time: String<20> = String::new();
+        let _ = write!(time, "{:02}:{:02}:{:02}", c.hour, c.minute, c.second);
+
+        for (row, text) in [
+            (0, title.as_str()),
+            (1, time.as_str()),
+            (2, "Press to snooze"),
+            (3, "Hold to stop"),
+        ] {
+            disp.set_cursor_position(row, (20 - text.len() as u8) / 2)?;
+            disp.write(text.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::{AlarmState, ClockState},
+        display::BufferedDisplay,
+        panel::{field::FieldPanel, time},
+    };
+
+    type Disp = BufferedDisplay<4, 20>;
+
+    #[derive(Default)]
+    struct FakeRtc {
+        state: ClockState,
+        alarm: AlarmState,
+        alarm_reset: bool,
+    }
+
+    impl Rtc for FakeRtc {
+        fn get_state(&self) -> ClockState {
+            self.state
+        }
+
+        fn set_state(&mut self, state: ClockState) {
+            self.state = state;
+        }
+
+        fn get_alarm(&self) -> AlarmState {
+            self.alarm
+        }
+
+        fn set_alarm(&mut self, alarm: AlarmState) {
+            self.alarm = alarm;
+        }
+
+        fn alarm_reset(&mut self) {
+            self.alarm_reset = true;
+        }
+    }
+
+    #[test]
+    fn edited_time_is_written_when_leaving_edit_mode() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, Settings::default());
+
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        app.handle_event(InterruptEvent::Encoder(3), &mut rtc);
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+
+        // the clock is not touched while editing
+        assert_eq!(rtc.state.hour, 0);
+        assert_eq!(app.state().clock.hour, 3);
+
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert_eq!(rtc.state.hour, 3);
+    }
+
+    #[test]
+    fn clock_is_not_rewound_when_nothing_was_edited() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, Settings::default());
+
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+
+        // the clock keeps running while the panel is editing
+        rtc.state.minute = 5;
+
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert_eq!(rtc.state.minute, 5);
+        assert_eq!(app.state().clock.minute, 5);
+    }
+
+    #[test]
+    fn next_alarm_is_programmed_into_the_clock() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut settings = Settings::default();
+        settings.alarms[1].enabled = true;
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, settings);
+
+        // 2000-01-01 is a saturday, so the weekend alarm is due
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert_eq!(
+            rtc.alarm,
+            AlarmState {
+                hour: 9,
+                minute: 0,
+                weekday: Some(6),
+                enabled: true,
+            }
+        );
+
+        // and after it went off the one of sunday
+        rtc.state.hour = 9;
+        app.handle_event(InterruptEvent::Alarm, &mut rtc);
+        assert_eq!(rtc.alarm.weekday, Some(7));
+
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        app.state_mut().settings.alarms[1].enabled = false;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert!(!rtc.alarm.enabled);
+    }
+
+    #[test]
+    fn alarm_disabled_while_editing_does_not_ring() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut settings = Settings::default();
+        settings.alarms[1].enabled = true;
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, settings);
+        app.state_mut().diagnostics.settings_source = SettingsSource::Stored { block: 1, seq: 0 };
+
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert!(rtc.alarm.enabled);
+
+        // the alarm is disabled while the panel is still editing
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        app.state_mut().settings.alarms[1].enabled = false;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert!(!rtc.alarm.enabled);
+
+        // an RTC alarm that went off anyway does not ring
+        rtc.state.hour = 9;
+        app.handle_event(InterruptEvent::Alarm, &mut rtc);
+        assert!(rtc.alarm_reset);
+        assert_eq!(app.take_ring_event(), None);
+        assert!(!app.ringer().is_ringing());
+    }
+
+    #[test]
+    fn legacy_alarm_rings_without_stored_settings() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, Settings::default());
+
+        rtc.state.hour = 9;
+        app.handle_event(InterruptEvent::Alarm, &mut rtc);
+        assert_eq!(app.take_ring_event(), Some(RingEvent::Ring(None)));
+    }
+
+    #[test]
+    fn alarm_rings_until_dismissed() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut settings = Settings::default();
+        settings.alarms[1].enabled = true;
+        settings.snooze_duration = 1;
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, settings);
+        let mut disp = Disp::new();
+
+        rtc.state.hour = 9;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        app.handle_event(InterruptEvent::Alarm, &mut rtc);
+        assert!(rtc.alarm_reset);
+        assert_eq!(app.take_ring_event(), Some(RingEvent::Ring(Some(1))));
+        assert_eq!(app.take_ring_event(), None);
+        assert!(app.render(&mut disp).unwrap() == CursorState::Off);
+        assert_eq!(&disp.rows()[0], b"      ALARM 2       ");
+        assert_eq!(&disp.rows()[1], b"      09:00:00      ");
+
+        // the encoder does nothing, a short press snoozes
+        app.handle_event(InterruptEvent::Encoder(1), &mut rtc);
+        assert!(app.ringer().is_ringing());
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        assert_eq!(app.take_ring_event(), Some(RingEvent::Snooze));
+        assert!(app.render(&mut disp).unwrap() == CursorState::Underline(0, 8));
+
+        // the panel can be used while snoozed
+        app.handle_event(InterruptEvent::Encoder(1), &mut rtc);
+        for _ in 0..60 * POLL_FREQ {
+            app.handle_event(InterruptEvent::Tick, &mut rtc);
+        }
+        assert_eq!(app.take_ring_event(), Some(RingEvent::Ring(Some(1))));
+
+        app.handle_event(InterruptEvent::LongPress, &mut rtc);
+        assert_eq!(app.take_ring_event(), Some(RingEvent::Dismiss));
+        assert!(!app.ringer().is_ringing());
+        assert!(app.render(&mut disp).unwrap() == CursorState::Underline(0, 11));
+    }
+
+    #[test]
+    fn snoozed_alarm_is_dismissed_by_long_press() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut settings = Settings::default();
+        settings.alarms[1].enabled = true;
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, settings);
+
+        rtc.state.hour = 9;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        app.handle_event(InterruptEvent::Alarm, &mut rtc);
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        assert!(app.ringer().is_snoozed());
+        app.take_ring_event();
+
+        // a long press while editing only leaves the field
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        app.handle_event(InterruptEvent::LongPress, &mut rtc);
+        assert!(app.ringer().is_snoozed());
+        assert_eq!(app.take_ring_event(), None);
+
+        app.handle_event(InterruptEvent::LongPress, &mut rtc);
+        assert_eq!(app.take_ring_event(), Some(RingEvent::Dismiss));
+        assert!(!app.ringer().is_snoozed());
+        for _ in 0..10 * 60 * POLL_FREQ {
+            app.handle_event(InterruptEvent::Tick, &mut rtc);
+        }
+        assert_eq!(app.take_ring_event(), None);
+    }
+
+    #[test]
+    fn smart_alarm_wakes_early_or_at_the_set_time() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut settings = Settings::default();
+        settings.alarms[1].enabled = true;
+        settings.alarms[1].window = 30;
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, settings);
+        let moving = Measurement {
+            gyro_mag_max: 0.5,
+            ..Measurement::default()
+        };
+
+        // movement before the window does not wake
+        rtc.state.hour = 8;
+        rtc.state.minute = 20;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        for _ in 0..3 {
+            app.record_measurement(&moving, &rtc);
+        }
+        assert_eq!(app.take_ring_event(), None);
+
+        rtc.state.minute = 40;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        app.record_measurement(&moving, &rtc);
+        assert_eq!(app.take_ring_event(), None);
+        app.record_measurement(&moving, &rtc);
+        app.record_measurement(&moving, &rtc);
+        assert_eq!(
+            app.take_ring_event(),
+            Some(RingEvent::Wake(1, WakeReason::LightSleep))
+        );
+        assert_eq!(app.state().stats.measurements, 6);
+        app.handle_event(InterruptEvent::LongPress, &mut rtc);
+        assert_eq!(app.take_ring_event(), Some(RingEvent::Dismiss));
+
+        // the alarm at the set time is already done with
+        rtc.state.hour = 9;
+        rtc.state.minute = 0;
+        app.handle_event(InterruptEvent::Alarm, &mut rtc);
+        assert_eq!(app.take_ring_event(), None);
+        assert!(!app.ringer().is_ringing());
+
+        // without movement on sunday it goes off at the set time
+        rtc.state.day = 2;
+        rtc.state.weekday = 7;
+        app.handle_event(InterruptEvent::Alarm, &mut rtc);
+        assert_eq!(
+            app.take_ring_event(),
+            Some(RingEvent::Wake(1, WakeReason::SetTime))
+        );
+        assert!(app.ringer().is_ringing());
+    }
+
+    #[test]
+    fn smart_alarm_follows_the_clock_while_editing() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut settings = Settings::default();
+        settings.alarms[1].enabled = true;
+        settings.alarms[1].window = 30;
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, settings);
+        let moving = Measurement {
+            gyro_mag_max: 0.5,
+            ..Measurement::default()
+        };
+
+        rtc.state.hour = 8;
+        rtc.state.minute = 20;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+
+        // the clock runs into the window while the time panel is editing
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        rtc.state.minute = 40;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert_eq!(app.state().clock.minute, 20);
+        for _ in 0..3 {
+            app.record_measurement(&moving, &rtc);
+        }
+        assert_eq!(
+            app.take_ring_event(),
+            Some(RingEvent::Wake(1, WakeReason::LightSleep))
+        );
+    }
+
+    #[test]
+    fn backlight_rises_before_alarm() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut settings = Settings::default();
+        settings.alarms[1].enabled = true;
+        settings.sunrise_duration = 10;
+        settings.snooze_duration = 1;
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, settings);
+
+        rtc.state.hour = 8;
+        rtc.state.minute = 50;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert_eq!(app.backlight(), 64);
+        rtc.state.minute = 55;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert_eq!(app.backlight(), 159);
+
+        // full brightness while ringing and snoozed, back to the day brightness once dismissed
+        rtc.state.hour = 9;
+        rtc.state.minute = 0;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        app.handle_event(InterruptEvent::Alarm, &mut rtc);
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        assert_eq!(app.backlight(), 255);
+        for _ in 0..60 * POLL_FREQ {
+            app.handle_event(InterruptEvent::Tick, &mut rtc);
+        }
+        assert!(app.ringer().is_ringing());
+        app.handle_event(InterruptEvent::LongPress, &mut rtc);
+        assert_eq!(app.backlight(), 64);
+    }
+
+    #[test]
+    fn first_input_wakes_dimmed_backlight() {
+        let mut rtc = FakeRtc::default();
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let settings = Settings {
+            backlight_timeout: 10,
+            ..Settings::default()
+        };
+        let mut app: App<Disp> = App::new(&mut panel, &rtc, settings);
+        rtc.state.hour = 12;
+
+        for _ in 0..10 * POLL_FREQ {
+            app.handle_event(InterruptEvent::Tick, &mut rtc);
+        }
+        assert_eq!(app.backlight(), 8);
+
+        // the press only wakes the backlight, the next one starts editing
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        assert_eq!(app.backlight(), 64);
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        app.handle_event(InterruptEvent::Encoder(1), &mut rtc);
+        assert_eq!(app.state().clock.hour, 13);
+
+        // at night the backlight is at the night brightness
+        app.handle_event(InterruptEvent::ShortPress, &mut rtc);
+        rtc.state.hour = 23;
+        app.handle_event(InterruptEvent::Tick, &mut rtc);
+        assert_eq!(app.backlight(), 16);
+    }
+}
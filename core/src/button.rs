@@ -0,0 +1,65 @@
+//! Detection of short and long presses on the encoder push button.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    ShortPress,
+    LongPress,
+}
+
+/// Counts how many consecutive polls the button has been held down.
+#[derive(Default)]
+pub struct ButtonState {
+    count: u32,
+}
+
+impl ButtonState {
+    pub const fn new() -> Self {
+        ButtonState { count: 0 }
+    }
+
+    /// Updates the state with the current button level, polled at a fixed interval.
+    ///
+    /// A `LongPress` is reported as soon as the button has been held for `timeout` polls, and a
+    /// `ShortPress` when it is released before that.
+    pub fn update(&mut self, pressed: bool, timeout: u32) -> Option<Button> {
+        let evt = match (pressed, self.count) {
+            (false, cnt) if 0 < cnt && cnt < timeout => Some(Button::ShortPress),
+            (true, cnt) if cnt < timeout => None,
+            (true, cnt) if cnt == timeout => Some(Button::LongPress),
+            (..) => None,
+        };
+
+        self.count = match pressed {
+            true => self.count.saturating_add(1), // avoid overflow
+            false => 0,
+        };
+
+        evt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(levels: &[bool]) -> Vec<Button> {
+        let mut state = ButtonState::new();
+        levels.iter().filter_map(|&l| state.update(l, 3)).collect()
+    }
+
+    #[test]
+    fn short_press_on_release() {
+        assert_eq!(
+            run(&[false, true, true, false, false]),
+            [Button::ShortPress]
+        );
+    }
+
+    #[test]
+    fn long_press_while_held() {
+        assert_eq!(
+            run(&[true, true, true, true, true, true, false]),
+            [Button::LongPress]
+        );
+    }
+}
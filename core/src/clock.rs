@@ -0,0 +1,112 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockState {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub weekday: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u8,
+}
+
+impl Default for ClockState {
+    fn default() -> Self {
+        ClockState {
+            hour: 0,
+            minute: 0,
+            second: 0,
+            // 2000-01-01 was a saturday
+            weekday: 6,
+            day: 1,
+            month: 1,
+            year: 0,
+        }
+    }
+}
+
+impl ClockState {
+    /// Sets the date, limiting the day to the length of the month and updating the weekday.
+    pub fn set_date(&mut self, day: u8, month: u8, year: u8) {
+        self.month = month.clamp(1, 12);
+        self.year = year.min(99);
+        self.day = day.clamp(1, days_in_month(self.month, self.year));
+        self.weekday = weekday(self.day, self.month, self.year);
+    }
+}
+
+/// Returns `true` if the year, counted from 2000 like `ClockState::year`, is a leap year. Every
+/// fourth year is one between 2000 and 2099, like the RTC assumes.
+pub fn is_leap_year(year: u8) -> bool {
+    year & 3 == 0
+}
+
+/// Number of days of the month, from 1 for january, in the year counted from 2000.
+pub fn days_in_month(month: u8, year: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Day of the week of the date, from 1 for monday to 7 for sunday like `ClockState::weekday`.
+pub fn weekday(day: u8, month: u8, year: u8) -> u8 {
+    // 2000-01-01 was a saturday
+    (crate::time::days_from_date(2000 + year as i64, month, day) + 5).rem_euclid(7) as u8 + 1
+}
+
+/// The alarm of the RTC, see `alarm::Scheduler` for the alarms of the user.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AlarmState {
+    pub hour: u8,
+    pub minute: u8,
+    /// Day of the week the alarm goes off on, or `None` for every day.
+    pub weekday: Option<u8>,
+    pub enabled: bool,
+}
+
+/// A real-time clock with a single alarm.
+pub trait Rtc {
+    fn get_state(&self) -> ClockState;
+    fn set_state(&mut self, state: ClockState);
+    fn get_alarm(&self) -> AlarmState;
+    fn set_alarm(&mut self, alarm: AlarmState);
+
+    /// Clears the alarm flag so that the alarm can trigger again.
+    fn alarm_reset(&mut self);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn month_lengths() {
+        assert_eq!(days_in_month(1, 21), 31);
+        assert_eq!(days_in_month(2, 21), 28);
+        assert_eq!(days_in_month(2, 24), 29);
+        assert_eq!(days_in_month(2, 0), 29);
+        assert_eq!(days_in_month(9, 21), 30);
+        assert_eq!(days_in_month(12, 99), 31);
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(weekday(1, 1, 0), 6);
+        assert_eq!(weekday(20, 9, 21), 1);
+        assert_eq!(weekday(29, 2, 24), 4);
+        assert_eq!(weekday(1, 3, 24), 5);
+        assert_eq!(weekday(31, 12, 99), 4);
+    }
+
+    #[test]
+    fn set_date_limits_the_day_and_updates_the_weekday() {
+        let mut c = ClockState::default();
+        c.set_date(31, 2, 23);
+        assert_eq!((c.day, c.month, c.year, c.weekday), (28, 2, 23, 2));
+
+        c.set_date(0, 13, 120);
+        assert_eq!((c.day, c.month, c.year, c.weekday), (1, 12, 99, 2));
+    }
+}
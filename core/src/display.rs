@@ -60,6 +60,12 @@ impl<const ROWS: usize, const COLUMNS: usize> BufferedDisplay<ROWS, COLUMNS> {
 
         Ok(changed)
     }
+
+    /// Returns the buffered contents, as they will be shown after the next `apply`.
+    pub fn rows(&self) -> &[[u8; COLUMNS]; ROWS] {
+        &self.backing_buffer
+    }
+
     #[allow(dead_code)]
     fn force_redraw(&mut self) {
         for row in 0..ROWS {
@@ -70,6 +76,12 @@ impl<const ROWS: usize, const COLUMNS: usize> BufferedDisplay<ROWS, COLUMNS> {
     }
 }
 
+impl<const ROWS: usize, const COLUMNS: usize> Default for BufferedDisplay<ROWS, COLUMNS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<const ROWS: usize, const COLUMNS: usize> Display for BufferedDisplay<ROWS, COLUMNS> {
     type Error = BufferedDisplayError;
 
@@ -101,13 +113,7 @@ impl<const ROWS: usize, const COLUMNS: usize> Display for BufferedDisplay<ROWS,
             self.current_column += 1;
             if self.current_column >= COLUMNS as u8 {
                 self.current_column = 0;
-                self.current_row += 1;
-
                 self.current_row = (self.current_row + 1) % ROWS as u8;
-
-                // if self.current_row >= ROWS as u8 {
-                //     self.current_row = 0;
-                // }
             }
         }
         Ok(())
@@ -201,6 +207,92 @@ impl<const ROWS: usize, const COLUMNS: usize> core::fmt::Write for BufferedDispl
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the operations performed on it as (row, column, character) writes.
+    #[derive(Default)]
+    struct RecordingDisplay {
+        cursor: (u8, u8),
+        writes: Vec<(u8, u8, u8)>,
+    }
+
+    impl Display for RecordingDisplay {
+        type Error = ();
+
+        fn clear(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_cursor_position(&mut self, row: u8, column: u8) -> Result<(), Self::Error> {
+            self.cursor = (row, column);
+            Ok(())
+        }
+
+        fn write(&mut self, characters: &[u8]) -> Result<(), Self::Error> {
+            for &c in characters {
+                self.writes.push((self.cursor.0, self.cursor.1, c));
+                self.cursor.1 += 1;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_at_cursor_position() {
+        let mut disp: BufferedDisplay<2, 8> = BufferedDisplay::new();
+        disp.set_cursor_position(1, 2).unwrap();
+        disp.write(b"abc").unwrap();
+
+        assert_eq!(&disp.rows()[0], b"        ");
+        assert_eq!(&disp.rows()[1], b"  abc   ");
+    }
+
+    #[test]
+    fn write_wraps_to_next_row() {
+        let mut disp: BufferedDisplay<2, 4> = BufferedDisplay::new();
+        disp.set_cursor_position(0, 2).unwrap();
+        disp.write(b"abcd").unwrap();
+        assert_eq!(&disp.rows()[0], b"  ab");
+        assert_eq!(&disp.rows()[1], b"cd  ");
+
+        // wrapping past the last row continues on the first
+        disp.set_cursor_position(1, 3).unwrap();
+        disp.write(b"xy").unwrap();
+        assert_eq!(&disp.rows()[0], b"y ab");
+        assert_eq!(&disp.rows()[1], b"cd x");
+    }
+
+    #[test]
+    fn cursor_out_of_bounds() {
+        let mut disp: BufferedDisplay<2, 4> = BufferedDisplay::new();
+        assert!(disp.set_cursor_position(2, 0).is_err());
+        assert!(disp.set_cursor_position(0, 4).is_err());
+    }
+
+    #[test]
+    fn apply_only_writes_changes() {
+        let mut disp: BufferedDisplay<2, 4> = BufferedDisplay::new();
+        let mut target = RecordingDisplay::default();
+
+        // the first apply draws everything
+        assert!(disp.apply(&mut target).unwrap());
+        assert_eq!(target.writes.len(), 8);
+
+        // nothing changed
+        target.writes.clear();
+        assert!(!disp.apply(&mut target).unwrap());
+        assert!(target.writes.is_empty());
+
+        disp.clear().unwrap();
+        disp.set_cursor_position(1, 1).unwrap();
+        disp.write(b"ab").unwrap();
+        assert!(disp.apply(&mut target).unwrap());
+        assert_eq!(target.writes, vec![(1, 1, b'a'), (1, 2, b'b')]);
+    }
+}
+
 // impl<I, E> Mpu6050<I> where I: Write<Error = E> + WriteRead<Error = E> {}
 
 /* Python driver source
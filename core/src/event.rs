@@ -1,8 +1,9 @@
-use core::{cell::RefCell, usize};
+use core::cell::RefCell;
 
 use cortex_m::interrupt::{CriticalSection, Mutex};
 
-#[derive(Debug, Clone, Copy, defmt::Format)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum InterruptEvent {
     Tick,
     Encoder(i8),
@@ -65,3 +66,46 @@ impl<const N: usize> EventQueue<N> {
         inner.tail.wrapping_sub(inner.head)
     }
 }
+
+impl<const N: usize> Default for EventQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_taken_in_order() {
+        let cs = unsafe { CriticalSection::new() };
+        let queue: EventQueue<4> = EventQueue::new();
+
+        assert!(queue.take(&cs).is_none());
+
+        queue.put(&cs, InterruptEvent::Tick);
+        queue.put(&cs, InterruptEvent::Encoder(-2));
+        queue.put(&cs, InterruptEvent::ShortPress);
+
+        assert!(matches!(queue.take(&cs), Some(InterruptEvent::Tick)));
+        assert!(matches!(queue.take(&cs), Some(InterruptEvent::Encoder(-2))));
+        assert!(matches!(queue.take(&cs), Some(InterruptEvent::ShortPress)));
+        assert!(queue.take(&cs).is_none());
+    }
+
+    #[test]
+    fn queue_wraps_around() {
+        let cs = unsafe { CriticalSection::new() };
+        let queue: EventQueue<3> = EventQueue::new();
+
+        for i in 0..10 {
+            queue.put(&cs, InterruptEvent::Encoder(i));
+            queue.put(&cs, InterruptEvent::LongPress);
+
+            assert!(matches!(queue.take(&cs), Some(InterruptEvent::Encoder(n)) if n == i));
+            assert!(matches!(queue.take(&cs), Some(InterruptEvent::LongPress)));
+            assert!(queue.take(&cs).is_none());
+        }
+    }
+}
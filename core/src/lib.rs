@@ -0,0 +1,81 @@
+//! Hardware-independent logic of the `dsaclk` firmware.
+//!
+//! Everything in here builds for both the microcontroller and the host, so it can be shared with
+//! the host tools and unit tested with `cargo test`.
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+pub mod alarm;
+pub mod app;
+pub mod button;
+pub mod clock;
+pub mod display;
+pub mod event;
+pub mod light;
+pub mod logger;
+pub mod mpu;
+pub mod panel;
+pub mod rtttl;
+pub mod settings;
+pub mod storage;
+pub mod time;
+pub mod tone;
+pub mod vec;
+pub mod wake;
+
+use clock::ClockState;
+use mpu::Measurement;
+use settings::Settings;
+use storage::{ErrorKind, SettingsSource};
+
+/// The state the panels operate on, shared with the main loop.
+#[derive(Debug)]
+pub struct SharedState {
+    pub clock: ClockState,
+    pub settings: Settings,
+    /// The last measurement of the MPU.
+    pub measurement: Option<Measurement>,
+    pub stats: Stats,
+    pub diagnostics: Diagnostics,
+}
+
+/// Counters since boot.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    /// Ticks of `app::POLL_FREQ`, counted by the `App`.
+    pub ticks: u32,
+    pub measurements: u32,
+    /// Lowest and highest mean temperature of the measurements.
+    pub temp_range: Option<(f32, f32)>,
+}
+
+impl Stats {
+    pub fn record(&mut self, m: &Measurement) {
+        self.measurements += 1;
+        let t = m.temp_mean;
+        self.temp_range = Some(match self.temp_range {
+            Some((min, max)) => (min.min(t), max.max(t)),
+            None => (t, t),
+        });
+    }
+}
+
+/// The state of the SD-card, kept up to date by the main loop.
+#[derive(Debug, Clone, Copy)]
+pub struct Diagnostics {
+    /// Block the log is appended to, or `None` if logging is disabled.
+    pub log_block: Option<u32>,
+    /// Why logging was disabled, if the card failed.
+    pub log_error: Option<ErrorKind>,
+    /// Where the settings were loaded from or last written to.
+    pub settings_source: SettingsSource,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Diagnostics {
+            log_block: None,
+            log_error: None,
+            settings_source: SettingsSource::Default,
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Brightness of the display backlight, which also serves as a wake light that brightens like a
+//! sunrise before an alarm, and dims when it is not used.
+use crate::{
+    app::POLL_FREQ,
+    settings::Settings,
+    time::{Duration, Timestamp},
+};
+
+/// Brightness the sunrise ends at, kept while the alarm rings or is snoozed.
+pub const SUNRISE_BRIGHTNESS: u8 = 255;
+
+/// Keeps track of the input, to dim the backlight after `Settings::backlight_timeout`.
+pub struct Backlight {
+    /// Ticks of `POLL_FREQ` since the last input.
+    idle_ticks: u32,
+}
+
+impl Default for Backlight {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backlight {
+    pub fn new() -> Self {
+        Backlight { idle_ticks: 0 }
+    }
+
+    pub fn tick(&mut self) {
+        self.idle_ticks = self.idle_ticks.saturating_add(1);
+    }
+
+    /// Registers an input. Returns `true` if the backlight was dimmed, in which case the input
+    /// should only wake it up.
+    pub fn input(&mut self, settings: &Settings) -> bool {
+        let was_idle = self.is_idle(settings);
+        self.idle_ticks = 0;
+        was_idle
+    }
+
+    /// Returns `true` once there was no input for `Settings::backlight_timeout`.
+    pub fn is_idle(&self, settings: &Settings) -> bool {
+        settings.backlight_timeout != 0
+            && self.idle_ticks >= settings.backlight_timeout as u32 * POLL_FREQ
+    }
+}
+
+/// Returns `true` if `now` is within the night hours of the settings.
+pub fn is_night(settings: &Settings, now: Timestamp) -> bool {
+    let hour = now.time_of_day().hours() as u8;
+    let (start, end) = (settings.night_start, settings.night_end);
+    if start <= end {
+        (start..end).contains(&hour)
+    } else {
+        hour >= start || hour < end
+    }
+}
+
+/// Brightness of the backlight at `now`, with the next alarm going off at `alarm`, `ringing`
+/// while an alarm rings or is snoozed and `idle` while the `Backlight` is idle.
+///
+/// It is the day or night brightness of the settings, at most `Settings::idle_brightness` while
+/// idle, except within `Settings::sunrise_duration` before the alarm, where it rises linearly to
+/// `SUNRISE_BRIGHTNESS`.
+pub fn brightness(
+    settings: &Settings,
+    now: Timestamp,
+    alarm: Option<Timestamp>,
+    ringing: bool,
+    idle: bool,
+) -> u8 {
+    if ringing {
+        return SUNRISE_BRIGHTNESS;
+    }
+
+    let base = match is_night(settings, now) {
+        true => settings.night_brightness,
+        false => settings.backlight_brightness,
+    };
+    let base = match idle {
+        true => base.min(settings.idle_brightness),
+        false => base,
+    };
+    let sunrise = Duration::from_minutes(settings.sunrise_duration as i64);
+    match alarm {
+        Some(at) if now < at && at - now < sunrise => {
+            let elapsed = (sunrise - (at - now)).seconds();
+            let rise = (SUNRISE_BRIGHTNESS - base) as i64 * elapsed / sunrise.seconds();
+            base + rise as u8
+        }
+        _ => base,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u8, minute: u8) -> Timestamp {
+        Timestamp::from_date_time(21, 9, 20, hour, minute, 0)
+    }
+
+    #[test]
+    fn night_spans_midnight() {
+        let mut settings = Settings::default();
+        assert!(is_night(&settings, at(22, 0)));
+        assert!(is_night(&settings, at(3, 0)));
+        assert!(!is_night(&settings, at(7, 0)));
+        assert!(!is_night(&settings, at(12, 0)));
+
+        settings.night_start = 1;
+        settings.night_end = 5;
+        assert!(is_night(&settings, at(1, 30)));
+        assert!(!is_night(&settings, at(23, 0)));
+    }
+
+    #[test]
+    fn sunrise_ramps_before_alarm() {
+        let settings = Settings {
+            backlight_brightness: 100,
+            night_brightness: 15,
+            sunrise_duration: 20,
+            ..Settings::default()
+        };
+        let alarm = Some(at(6, 30));
+
+        assert_eq!(brightness(&settings, at(6, 0), alarm, false, false), 15);
+        assert_eq!(brightness(&settings, at(6, 10), alarm, false, false), 15);
+        assert_eq!(brightness(&settings, at(6, 20), alarm, false, false), 135);
+        assert_eq!(brightness(&settings, at(6, 29), alarm, false, false), 243);
+        assert_eq!(brightness(&settings, at(6, 30), alarm, true, false), 255);
+
+        // dismissed, it falls back to the brightness of the night or the day
+        assert_eq!(brightness(&settings, at(6, 35), alarm, false, false), 15);
+        assert_eq!(brightness(&settings, at(7, 5), alarm, false, false), 100);
+    }
+
+    #[test]
+    fn no_sunrise_without_duration() {
+        let settings = Settings::default();
+        let alarm = Some(at(6, 30));
+        assert_eq!(brightness(&settings, at(6, 29), alarm, false, false), 16);
+        assert_eq!(brightness(&settings, at(12, 0), None, false, false), 64);
+    }
+
+    #[test]
+    fn dims_without_input() {
+        let settings = Settings {
+            backlight_timeout: 30,
+            idle_brightness: 0,
+            sunrise_duration: 10,
+            ..Settings::default()
+        };
+        let mut backlight = Backlight::new();
+        for _ in 0..30 * POLL_FREQ - 1 {
+            backlight.tick();
+        }
+        assert!(!backlight.is_idle(&settings));
+        assert!(!backlight.input(&settings));
+
+        for _ in 0..30 * POLL_FREQ {
+            backlight.tick();
+        }
+        assert!(backlight.is_idle(&settings));
+        assert_eq!(brightness(&settings, at(12, 0), None, false, true), 0);
+        // the sunrise still starts from the dimmed brightness
+        let alarm = Some(at(6, 30));
+        assert_eq!(brightness(&settings, at(6, 25), alarm, false, true), 127);
+
+        // the first input wakes it up
+        assert!(backlight.input(&settings));
+        assert!(!backlight.is_idle(&settings));
+        assert!(!backlight.input(&settings));
+
+        // unless it never dims
+        let settings = Settings {
+            backlight_timeout: 0,
+            ..settings
+        };
+        for _ in 0..600 * POLL_FREQ {
+            backlight.tick();
+        }
+        assert!(!backlight.is_idle(&settings));
+    }
+}
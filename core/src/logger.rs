@@ -0,0 +1,890 @@
+use crc::{Crc, CRC_16_IBM_SDLC};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alarm::RingEvent,
+    clock::ClockState,
+    mpu,
+    storage::{
+        self, BlockDevice, Error, LEGACY_LOG_BLOCK_IDX, LOGGER_BLOCK_START_IDX,
+        LOGGER_DATA_START_IDX, SD_BLOCK_SIZE,
+    },
+};
+
+/// Identifies the `LogHeader` block.
+pub const LOG_MAGIC: [u8; 4] = *b"DSAL";
+
+/// Identifies the `BlockHeader` at the start of every block of log entries.
+pub const BLOCK_MAGIC: [u8; 4] = *b"DSAB";
+
+/// Identifies the `LegacyLog` block.
+pub const LEGACY_MAGIC: [u8; 4] = *b"DSAO";
+
+/// Version of the block layout, ie the headers and how entries are stored in the blocks.
+///
+/// Version 1 stored the bare postcard-serialized entries, version 2 stores every entry as a frame
+/// (see `encode_frame`) so that readers can skip damaged data.
+pub const LOG_FORMAT_VERSION: u16 = 2;
+
+/// Version of the serialized `LogEntry`. Must be incremented whenever `LogEntry`, `LogContents`
+/// or anything they contain changes.
+///
+/// Schema 2 added `LogContents::Ring`, schema 3 added `RingEvent::Wake`.
+pub const LOG_ENTRY_SCHEMA: u16 = 3;
+
+/// Oldest schema whose entries are still read the same by the current one, because only new
+/// `LogContents` variants were added since. Logs of such a schema are upgraded when opened.
+pub const FIRST_COMPATIBLE_SCHEMA: u16 = 1;
+
+/// Size of the serialized `BlockHeader`.
+pub const BLOCK_HEADER_SIZE: usize = 10;
+
+/// Number of bytes of serialized entries stored in each block after the `BlockHeader`.
+pub const BLOCK_PAYLOAD_SIZE: usize = SD_BLOCK_SIZE - BLOCK_HEADER_SIZE;
+
+/// Upper bound of a serialized `LogEntry` followed by its CRC.
+const MAX_ENTRY_SIZE: usize = 64;
+
+/// Checksum appended to every serialized `LogEntry`.
+const ENTRY_CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+
+/// Serializes the entry followed by its CRC, COBS-encoded and terminated by a zero byte, into the
+/// start of `buf`. Returns the length of the frame.
+///
+/// Since zero bytes only appear at the end of frames, a reader can always resynchronize on the
+/// next frame after damaged data.
+pub fn encode_frame(entry: &LogEntry, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+    let mut raw = [0u8; MAX_ENTRY_SIZE];
+    let len = postcard::to_slice(entry, &mut raw[..MAX_ENTRY_SIZE - 2])?.len();
+
+    let crc = ENTRY_CRC.checksum(&raw[..len]);
+    raw[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+
+    let encoded_len =
+        cobs::try_encode(&raw[..len + 2], buf).map_err(|_| postcard::Error::SerializeBufferFull)?;
+    *buf.get_mut(encoded_len)
+        .ok_or(postcard::Error::SerializeBufferFull)? = 0;
+
+    Ok(encoded_len + 1)
+}
+
+/// Decodes a frame written by `encode_frame`, given without its terminating zero byte. Returns
+/// `None` if the frame is damaged.
+pub fn decode_frame(frame: &[u8]) -> Option<LogEntry> {
+    let mut raw = [0u8; MAX_ENTRY_SIZE];
+    let len = cobs::decode(frame, &mut raw).ok()?;
+    if len < 2 {
+        return None;
+    }
+
+    let (data, crc) = raw[..len].split_at(len - 2);
+    if ENTRY_CRC.checksum(data).to_le_bytes() != crc {
+        return None;
+    }
+
+    postcard::from_bytes(data).ok()
+}
+
+/// Stored in the `LOGGER_BLOCK_START_IDX` block, describing the log that follows it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LogHeader {
+    pub magic: [u8; 4],
+    pub version: u16,
+    pub schema: u16,
+    /// Sequence number of the block at `LOGGER_DATA_START_IDX`. A log following a `LegacyLog`
+    /// starts further on, at the sequence number this gives its first block.
+    pub first_seq: u32,
+}
+
+impl LogHeader {
+    /// A header for a log in the current format.
+    pub const fn new(first_seq: u32) -> Self {
+        Self {
+            magic: LOG_MAGIC,
+            version: LOG_FORMAT_VERSION,
+            schema: LOG_ENTRY_SCHEMA,
+            first_seq,
+        }
+    }
+
+    /// A header for a log in the current format whose first block at `addr` has the sequence
+    /// number `seq`.
+    pub const fn starting_at(addr: u32, seq: u32) -> Self {
+        Self::new(seq.wrapping_sub(addr.wrapping_sub(LOGGER_DATA_START_IDX)))
+    }
+
+    /// Reads the header from the block, returning `None` if it does not hold one.
+    pub fn parse(block: &[u8; SD_BLOCK_SIZE]) -> Option<Self> {
+        postcard::from_bytes::<Self>(block)
+            .ok()
+            .filter(|h| h.magic == LOG_MAGIC)
+    }
+
+    /// Returns `true` if the log is in the format written by this version, or one that can be
+    /// appended to after upgrading the schema.
+    pub fn is_supported(&self) -> bool {
+        self.version == LOG_FORMAT_VERSION
+            && (FIRST_COMPATIBLE_SCHEMA..=LOG_ENTRY_SCHEMA).contains(&self.schema)
+    }
+
+    /// The sequence number the block at `addr` has if it belongs to this log.
+    pub fn seq_at(&self, addr: u32) -> u32 {
+        self.first_seq
+            .wrapping_add(addr.wrapping_sub(LOGGER_DATA_START_IDX))
+    }
+}
+
+/// Stored at the start of every block of log entries.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct BlockHeader {
+    pub magic: [u8; 4],
+    pub version: u16,
+    /// Incremented for every block written, continuing across logs so that blocks left over from
+    /// an earlier log are not mistaken for a part of the current one.
+    pub seq: u32,
+}
+
+impl BlockHeader {
+    /// Reads the header from the start of the block, returning `None` if it does not hold one.
+    pub fn parse(block: &[u8; SD_BLOCK_SIZE]) -> Option<Self> {
+        postcard::from_bytes::<Self>(&block[..BLOCK_HEADER_SIZE])
+            .ok()
+            .filter(|h| h.magic == BLOCK_MAGIC)
+    }
+}
+
+/// Stored in the `LEGACY_LOG_BLOCK_IDX` block of a card holding the bare entries written before
+/// the `LogHeader` was introduced, from `LOGGER_BLOCK_START_IDX` up to `end`. The log continuing
+/// it has its `LogHeader` in the `end` block, followed by its entries.
+///
+/// The end of the old log was only kept in the `settings::v0::Settings`, which are replaced on the
+/// first change of the settings, so it is stored here when the log is continued.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LegacyLog {
+    pub magic: [u8; 4],
+    pub end: u32,
+}
+
+impl LegacyLog {
+    /// Finds the end of the bare entries on a card that holds them at `LOGGER_BLOCK_START_IDX`,
+    /// returning `None` if it is unknown.
+    pub fn find<B: BlockDevice>(dev: &mut B) -> Result<Option<Self>, Error<B::Error>> {
+        let mut block = [0u8; SD_BLOCK_SIZE];
+        dev.read_block(LEGACY_LOG_BLOCK_IDX, &mut block)
+            .map_err(Error::DeviceError)?;
+
+        let stored = postcard::from_bytes::<Self>(&block)
+            .ok()
+            .filter(|l| l.magic == LEGACY_MAGIC);
+        Ok(match stored {
+            Some(legacy) => Some(legacy),
+            None => storage::legacy_log_end(dev)?.map(|end| LegacyLog {
+                magic: LEGACY_MAGIC,
+                end,
+            }),
+        })
+    }
+}
+
+/// Finds the block holding the `LogHeader`, which follows the bare entries written before it was
+/// introduced on cards still holding them. Returns the block along with its contents.
+pub fn find_log_header<B: BlockDevice>(
+    dev: &mut B,
+) -> Result<(u32, [u8; SD_BLOCK_SIZE]), Error<B::Error>> {
+    let mut block = [0u8; SD_BLOCK_SIZE];
+    dev.read_block(LOGGER_BLOCK_START_IDX, &mut block)
+        .map_err(Error::DeviceError)?;
+    if LogHeader::parse(&block).is_some() || block.iter().all(|&b| b == 0) {
+        return Ok((LOGGER_BLOCK_START_IDX, block));
+    }
+
+    match LegacyLog::find(dev)? {
+        Some(legacy) => {
+            dev.read_block(legacy.end, &mut block)
+                .map_err(Error::DeviceError)?;
+            Ok((legacy.end, block))
+        }
+        None => Err(Error::UnsupportedLogVersion {
+            version: 0,
+            schema: 0,
+        }),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogEntry {
+    pub timestamp: LogTimestamp,
+    pub contents: LogContents,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum LogContents {
+    Measurement(mpu::Measurement),
+    Alarm(),
+    /// A transition of the `alarm::Ringer`.
+    Ring(RingEvent),
+}
+
+/// The time instant a log entry is logged.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogTimestamp {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u8,
+}
+
+impl From<&ClockState> for LogTimestamp {
+    fn from(s: &ClockState) -> Self {
+        Self {
+            hour: s.hour,
+            minute: s.minute,
+            second: s.second,
+            day: s.day,
+            month: s.month,
+            year: s.year,
+        }
+    }
+}
+
+/// Buffers `LogEntry` frames and hands them out one block payload at a time.
+pub struct LogBuffer {
+    buffer: [u8; SD_BLOCK_SIZE * 2],
+    current_idx: usize,
+}
+
+impl LogBuffer {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0u8; SD_BLOCK_SIZE * 2],
+            current_idx: 0,
+        }
+    }
+
+    /// Serializes the LogEntry as a frame to the end of the buffer.
+    /// Note that this assumes that the entire frame fits into the remaining buffer space, which
+    /// holds as long as complete blocks are taken out after every push.
+    pub fn push(&mut self, entry: &LogEntry) -> Result<(), postcard::Error> {
+        // get a mutable slice of the remaining content in the buffer array
+        let buff = &mut self.buffer[self.current_idx..];
+
+        let len = encode_frame(entry, buff)?;
+
+        // increment next buffer index
+        self.current_idx += len;
+        Ok(())
+    }
+
+    /// Returns `true` if at least one complete block payload is buffered.
+    pub fn has_full_block(&self) -> bool {
+        self.current_idx >= BLOCK_PAYLOAD_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current_idx == 0
+    }
+
+    /// Removes the first block payload from the buffer and shifts the remaining contents.
+    /// If the first payload is not full (ie `current_idx < BLOCK_PAYLOAD_SIZE`), the remaining
+    /// bytes are zeroed (just for keeping the blocks nice and tidy when reading later).
+    /// Returns `None` if there are no buffered bytes.
+    pub fn take_block(&mut self) -> Option<[u8; BLOCK_PAYLOAD_SIZE]> {
+        if self.current_idx == 0 {
+            return None;
+        }
+
+        let mut block = [0u8; BLOCK_PAYLOAD_SIZE];
+
+        if self.current_idx < BLOCK_PAYLOAD_SIZE {
+            block[..self.current_idx].copy_from_slice(&self.buffer[..self.current_idx]);
+            self.current_idx = 0;
+        } else {
+            block.copy_from_slice(&self.buffer[..BLOCK_PAYLOAD_SIZE]);
+
+            // shift remaining data to the beginning of the buffer
+            self.current_idx -= BLOCK_PAYLOAD_SIZE;
+            self.buffer
+                .copy_within(BLOCK_PAYLOAD_SIZE..BLOCK_PAYLOAD_SIZE + self.current_idx, 0);
+        }
+
+        // the buffer is kept zeroed after the used part
+        self.buffer[self.current_idx..].fill(0);
+
+        Some(block)
+    }
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Number of blocks after the end found by `find_log_end` that are checked for more of the log,
+/// in case a block in the middle of it was damaged.
+const TAIL_LOOKAHEAD: u32 = 8;
+
+/// Returns `true` if the block at `addr` holds a part of the log described by `header`.
+fn holds_log_block<B: BlockDevice>(
+    dev: &mut B,
+    header: &LogHeader,
+    addr: u32,
+) -> Result<bool, Error<B::Error>> {
+    let mut block = [0u8; SD_BLOCK_SIZE];
+    dev.read_block(addr, &mut block)
+        .map_err(Error::DeviceError)?;
+
+    Ok(BlockHeader::parse(&block)
+        .is_some_and(|h| h.version == header.version && h.seq == header.seq_at(addr)))
+}
+
+/// Finds the first block after the log described by `header`, whose first block is `start`, ie
+/// the next block to write.
+///
+/// Since the blocks are written in order, the ones holding the log are found by a binary search
+/// over their sequence numbers. Blocks left over from earlier logs have lower sequence numbers
+/// than the ones expected at their positions and are therefore not mistaken for a part of the log.
+pub fn find_log_end<B: BlockDevice>(
+    dev: &mut B,
+    header: &LogHeader,
+    start: u32,
+) -> Result<u32, Error<B::Error>> {
+    let block_count = dev.block_count();
+    let mut start = start;
+
+    loop {
+        // the log holds all blocks before `start` and none from `end`
+        let mut end = block_count;
+        while start < end {
+            let mid = start + (end - start) / 2;
+            if holds_log_block(dev, header, mid)? {
+                start = mid + 1;
+            } else {
+                end = mid;
+            }
+        }
+
+        // a damaged block is taken as the end of the log, so make sure it does not continue
+        let mut found = None;
+        for addr in start + 1..block_count.min(start + 1 + TAIL_LOOKAHEAD) {
+            if holds_log_block(dev, header, addr)? {
+                found = Some(addr);
+                break;
+            }
+        }
+
+        match found {
+            Some(addr) => start = addr + 1,
+            None => return Ok(start),
+        }
+    }
+}
+
+/// Writes `LogEntry` values to consecutive blocks of a `BlockDevice`, continuing after the end of
+/// the log found when it is opened.
+pub struct Logger {
+    buffer: LogBuffer,
+    header: LogHeader,
+    /// The next block to write to.
+    block: u32,
+}
+
+impl Logger {
+    /// Opens the log on the device for appending, starting a new log if the device holds none.
+    /// The bare entries written before the `LogHeader` was introduced are continued by a new log
+    /// after them, see `LegacyLog`. Logs in any other format than the current one are left
+    /// untouched and reported as `Error::UnsupportedLogVersion`.
+    pub fn open<B: BlockDevice>(dev: &mut B) -> Result<Self, Error<B::Error>> {
+        let (addr, block) = find_log_header(dev)?;
+
+        match LogHeader::parse(&block) {
+            Some(old) if old.is_supported() => {
+                let block = find_log_end(dev, &old, addr + 1)?;
+
+                // the entries of an older schema are read the same, so the new entries can be
+                // appended once the header says they may be of the current schema
+                let header = LogHeader {
+                    schema: LOG_ENTRY_SCHEMA,
+                    ..old
+                };
+                if header != old {
+                    let mut data = [0u8; SD_BLOCK_SIZE];
+                    postcard::to_slice(&header, &mut data).map_err(Error::PostcardError)?;
+                    dev.write_block(addr, &data).map_err(Error::DeviceError)?;
+                }
+
+                Ok(Self {
+                    buffer: LogBuffer::new(),
+                    block,
+                    header,
+                })
+            }
+            Some(header) => Err(Error::UnsupportedLogVersion {
+                version: header.version,
+                schema: header.schema,
+            }),
+            // a blank card, or one with only the bare entries written before there was a header
+            None if block.iter().all(|&b| b == 0) => Self::create(dev),
+            None => Err(Error::UnsupportedLogVersion {
+                version: 0,
+                schema: 0,
+            }),
+        }
+    }
+
+    /// Starts a new, empty log on the device, replacing any existing one but the bare entries
+    /// written before the `LogHeader` was introduced. The sequence numbers continue after the ones
+    /// of an existing log in the current format.
+    pub fn create<B: BlockDevice>(dev: &mut B) -> Result<Self, Error<B::Error>> {
+        let (addr, block) = find_log_header(dev)?;
+
+        let first_seq = match LogHeader::parse(&block) {
+            Some(old) if old.is_supported() => old.seq_at(find_log_end(dev, &old, addr + 1)?),
+            _ => 0,
+        };
+        let header = LogHeader::starting_at(addr + 1, first_seq);
+
+        // keep the end of the bare entries, as the settings holding it are replaced eventually
+        if addr != LOGGER_BLOCK_START_IDX {
+            let legacy = LegacyLog {
+                magic: LEGACY_MAGIC,
+                end: addr,
+            };
+            let mut block = [0u8; SD_BLOCK_SIZE];
+            postcard::to_slice(&legacy, &mut block).map_err(Error::PostcardError)?;
+            dev.write_block(LEGACY_LOG_BLOCK_IDX, &block)
+                .map_err(Error::DeviceError)?;
+        }
+
+        let mut block = [0u8; SD_BLOCK_SIZE];
+        postcard::to_slice(&header, &mut block).map_err(Error::PostcardError)?;
+        dev.write_block(addr, &block).map_err(Error::DeviceError)?;
+
+        Ok(Self {
+            buffer: LogBuffer::new(),
+            header,
+            block: addr + 1,
+        })
+    }
+
+    /// The header of the log being written.
+    pub fn header(&self) -> &LogHeader {
+        &self.header
+    }
+
+    /// The block the next buffered data will be written to.
+    pub fn next_block(&self) -> u32 {
+        self.block
+    }
+
+    /// Appends an new LogEntry containing the LogContents to the log
+    pub fn append<B: BlockDevice>(
+        &mut self,
+        clock: &ClockState,
+        contents: LogContents,
+        dev: &mut B,
+    ) -> Result<(), Error<B::Error>> {
+        // construct a new logger entry with timestamp
+        let entry = LogEntry {
+            timestamp: clock.into(),
+            contents,
+        };
+
+        self.buffer.push(&entry).map_err(Error::PostcardError)?;
+
+        // write the blocks while we have enough serialized data available
+        while self.buffer.has_full_block() {
+            self.write_first_block(dev)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the first block in the buffer to the device and moves on to the next block.
+    /// If the buffer is empty, the function does nothing.
+    fn write_first_block<B: BlockDevice>(&mut self, dev: &mut B) -> Result<(), Error<B::Error>> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        if self.block >= dev.block_count() {
+            return Err(Error::DeviceFull);
+        }
+
+        let header = BlockHeader {
+            magic: BLOCK_MAGIC,
+            version: LOG_FORMAT_VERSION,
+            seq: self.header.seq_at(self.block),
+        };
+
+        let mut data_to_write = [0u8; SD_BLOCK_SIZE];
+        postcard::to_slice(&header, &mut data_to_write[..BLOCK_HEADER_SIZE])
+            .map_err(Error::PostcardError)?;
+
+        // cannot fail since the buffer is not empty
+        data_to_write[BLOCK_HEADER_SIZE..].copy_from_slice(&self.buffer.take_block().unwrap());
+        dev.write_block(self.block, &data_to_write)
+            .map_err(Error::DeviceError)?;
+
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Wrote block at address {}", self.block);
+
+        self.block += 1;
+
+        Ok(())
+    }
+
+    /// Forces the Logger to write the rest of the buffered serialized LogEntries to the device.
+    /// Should be called when one wants to stop logging.
+    pub fn flush<B: BlockDevice>(&mut self, dev: &mut B) -> Result<(), Error<B::Error>> {
+        // write blocks until there is no more data to write
+        while !self.buffer.is_empty() {
+            self.write_first_block(dev)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::MemBlockDevice, vec::Vec3f};
+
+    fn entry(second: u8) -> LogEntry {
+        LogEntry {
+            timestamp: (&ClockState {
+                second,
+                ..ClockState::default()
+            })
+                .into(),
+            contents: LogContents::Measurement(mpu::Measurement {
+                acc_mean: Vec3f(0.0, 0.0, 1.0),
+                temp_mean: 21.5,
+                gyro_mag_max: 0.25,
+            }),
+        }
+    }
+
+    fn frame(entry: &LogEntry) -> Vec<u8> {
+        let mut buf = [0u8; 2 * MAX_ENTRY_SIZE];
+        let len = encode_frame(entry, &mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    fn entry_len() -> usize {
+        frame(&entry(0)).len()
+    }
+
+    #[test]
+    fn frame_roundtrip() {
+        let frame = frame(&entry(42));
+        assert_eq!(frame.last(), Some(&0));
+        assert!(!frame[..frame.len() - 1].contains(&0));
+
+        let decoded = decode_frame(&frame[..frame.len() - 1]).unwrap();
+        assert_eq!(decoded.timestamp.second, 42);
+    }
+
+    #[test]
+    fn damaged_frames_are_rejected() {
+        let frame = frame(&entry(42));
+        let frame = &frame[..frame.len() - 1];
+
+        for bit in 0..frame.len() * 8 {
+            let mut damaged = frame.to_vec();
+            damaged[bit / 8] ^= 1 << (bit % 8);
+            assert!(decode_frame(&damaged).is_none(), "bit {} not detected", bit);
+        }
+
+        assert!(decode_frame(&frame[..frame.len() - 1]).is_none());
+        assert!(decode_frame(&[]).is_none());
+    }
+
+    #[test]
+    fn empty_buffer_has_no_block() {
+        let mut buffer = LogBuffer::new();
+        assert!(buffer.is_empty());
+        assert!(buffer.take_block().is_none());
+    }
+
+    #[test]
+    fn partial_block_is_zero_padded() {
+        let mut buffer = LogBuffer::new();
+        buffer.push(&entry(1)).unwrap();
+        assert!(!buffer.has_full_block());
+
+        let block = buffer.take_block().unwrap();
+        let len = entry_len();
+        assert_eq!(&block[..len], &frame(&entry(1))[..]);
+        assert!(block[len..].iter().all(|&b| b == 0));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn entries_continue_across_blocks() {
+        let mut buffer = LogBuffer::new();
+        let mut stream = Vec::new();
+        let mut blocks = Vec::new();
+
+        for i in 0..100 {
+            buffer.push(&entry(i % 60)).unwrap();
+            stream.extend(frame(&entry(i % 60)));
+            while buffer.has_full_block() {
+                blocks.extend(buffer.take_block().unwrap());
+            }
+        }
+        blocks.extend(buffer.take_block().unwrap());
+
+        assert_eq!(blocks.len() % BLOCK_PAYLOAD_SIZE, 0);
+        assert_eq!(&blocks[..stream.len()], &stream[..]);
+        assert!(blocks[stream.len()..].iter().all(|&b| b == 0));
+    }
+
+    fn append_all(logger: &mut Logger, dev: &mut MemBlockDevice, count: usize) {
+        for i in 0..count {
+            logger
+                .append(&ClockState::default(), entry((i % 60) as u8).contents, dev)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn logger_writes_blocks() {
+        let mut dev = MemBlockDevice::new(64);
+        let mut logger = Logger::open(&mut dev).unwrap();
+
+        let per_block = BLOCK_PAYLOAD_SIZE / entry_len();
+        append_all(&mut logger, &mut dev, per_block + 1);
+        assert_eq!(logger.next_block(), LOGGER_DATA_START_IDX + 1);
+
+        // flushing writes the remaining partial block
+        logger.flush(&mut dev).unwrap();
+        assert_eq!(logger.next_block(), LOGGER_DATA_START_IDX + 2);
+        logger.flush(&mut dev).unwrap();
+        assert_eq!(logger.next_block(), LOGGER_DATA_START_IDX + 2);
+
+        let last = dev.blocks_mut()[LOGGER_DATA_START_IDX as usize + 1];
+        assert_eq!(BlockHeader::parse(&last).unwrap().seq, 1);
+        assert!(
+            last[BLOCK_HEADER_SIZE + (per_block + 1) * entry_len() - BLOCK_PAYLOAD_SIZE..]
+                .iter()
+                .all(|&b| b == 0)
+        );
+
+        // nothing but the log was written
+        assert!(dev.blocks_mut()[..LOGGER_BLOCK_START_IDX as usize]
+            .iter()
+            .all(|b| b.iter().all(|&b| b == 0)));
+    }
+
+    #[test]
+    fn logger_stops_when_device_is_full() {
+        let mut dev = MemBlockDevice::new(LOGGER_DATA_START_IDX + 1);
+        let mut logger = Logger::open(&mut dev).unwrap();
+
+        let mut result = Ok(());
+        for i in 0..100 {
+            result = logger.append(&ClockState::default(), entry(i).contents, &mut dev);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        assert!(matches!(result, Err(Error::DeviceFull)));
+        assert_eq!(logger.next_block(), LOGGER_DATA_START_IDX + 1);
+    }
+
+    #[test]
+    fn header_sizes() {
+        let header = BlockHeader {
+            magic: BLOCK_MAGIC,
+            version: LOG_FORMAT_VERSION,
+            seq: u32::MAX,
+        };
+        assert_eq!(
+            postcard::to_stdvec(&header).unwrap().len(),
+            BLOCK_HEADER_SIZE
+        );
+        assert!(
+            postcard::to_stdvec(&LogHeader::new(u32::MAX))
+                .unwrap()
+                .len()
+                <= SD_BLOCK_SIZE
+        );
+    }
+
+    #[test]
+    fn open_creates_log_on_blank_device() {
+        let mut dev = MemBlockDevice::new(64);
+        let logger = Logger::open(&mut dev).unwrap();
+
+        assert_eq!(*logger.header(), LogHeader::new(0));
+        assert_eq!(logger.next_block(), LOGGER_DATA_START_IDX);
+
+        let header = LogHeader::parse(&dev.blocks_mut()[LOGGER_BLOCK_START_IDX as usize]);
+        assert_eq!(header, Some(LogHeader::new(0)));
+    }
+
+    #[test]
+    fn open_continues_at_end_of_log() {
+        let mut dev = MemBlockDevice::new(1024);
+
+        for written in 0..40 {
+            let mut logger = Logger::open(&mut dev).unwrap();
+            assert_eq!(logger.next_block(), LOGGER_DATA_START_IDX + written);
+
+            // one partially filled block per session
+            append_all(&mut logger, &mut dev, 3);
+            logger.flush(&mut dev).unwrap();
+        }
+
+        let block = dev.blocks_mut()[LOGGER_DATA_START_IDX as usize + 39];
+        assert_eq!(
+            BlockHeader::parse(&block),
+            Some(BlockHeader {
+                magic: BLOCK_MAGIC,
+                version: LOG_FORMAT_VERSION,
+                seq: 39,
+            })
+        );
+    }
+
+    #[test]
+    fn end_search_skips_damaged_block() {
+        let mut dev = MemBlockDevice::new(1024);
+        let mut logger = Logger::open(&mut dev).unwrap();
+        for _ in 0..100 {
+            append_all(&mut logger, &mut dev, 1);
+            logger.flush(&mut dev).unwrap();
+        }
+
+        // the binary search looks at the middle of the device first, then narrows down to here
+        dev.blocks_mut()[LOGGER_DATA_START_IDX as usize + 63][0] ^= 0xff;
+
+        let header = *logger.header();
+        assert_eq!(
+            find_log_end(&mut dev, &header, LOGGER_DATA_START_IDX).unwrap(),
+            LOGGER_DATA_START_IDX + 100
+        );
+    }
+
+    #[test]
+    fn new_log_does_not_continue_old_one() {
+        let mut dev = MemBlockDevice::new(1024);
+        let mut logger = Logger::open(&mut dev).unwrap();
+        for _ in 0..20 {
+            append_all(&mut logger, &mut dev, 1);
+            logger.flush(&mut dev).unwrap();
+        }
+
+        let mut logger = Logger::create(&mut dev).unwrap();
+        assert_eq!(logger.header().first_seq, 20);
+        append_all(&mut logger, &mut dev, 1);
+        logger.flush(&mut dev).unwrap();
+
+        // the blocks of the old log after the first one are not taken as a part of the new one
+        let logger = Logger::open(&mut dev).unwrap();
+        assert_eq!(logger.next_block(), LOGGER_DATA_START_IDX + 1);
+    }
+
+    #[test]
+    fn open_upgrades_compatible_schema() {
+        let mut dev = MemBlockDevice::new(1024);
+        let mut logger = Logger::open(&mut dev).unwrap();
+        append_all(&mut logger, &mut dev, 2);
+        logger.flush(&mut dev).unwrap();
+
+        // the same log as written with the first schema
+        let old = LogHeader {
+            schema: FIRST_COMPATIBLE_SCHEMA,
+            ..LogHeader::new(0)
+        };
+        let mut block = [0u8; SD_BLOCK_SIZE];
+        postcard::to_slice(&old, &mut block).unwrap();
+        dev.blocks_mut()[LOGGER_BLOCK_START_IDX as usize] = block;
+
+        let logger = Logger::open(&mut dev).unwrap();
+        assert_eq!(*logger.header(), LogHeader::new(0));
+        assert_eq!(logger.next_block(), LOGGER_DATA_START_IDX + 1);
+        let header = LogHeader::parse(&dev.blocks_mut()[LOGGER_BLOCK_START_IDX as usize]);
+        assert_eq!(header, Some(LogHeader::new(0)));
+    }
+
+    #[test]
+    fn open_refuses_unknown_formats() {
+        let mut dev = MemBlockDevice::new(64);
+
+        // a log written by a newer version
+        let mut block = [0u8; SD_BLOCK_SIZE];
+        let header = LogHeader {
+            version: LOG_FORMAT_VERSION + 1,
+            ..LogHeader::new(0)
+        };
+        postcard::to_slice(&header, &mut block).unwrap();
+        dev.blocks_mut()[LOGGER_BLOCK_START_IDX as usize] = block;
+
+        assert!(matches!(
+            Logger::open(&mut dev),
+            Err(Error::UnsupportedLogVersion { version, .. }) if version == LOG_FORMAT_VERSION + 1
+        ));
+
+        // bare entries from before the header was introduced
+        let mut block = [0u8; SD_BLOCK_SIZE];
+        postcard::to_slice(&entry(1), &mut block).unwrap();
+        dev.blocks_mut()[LOGGER_BLOCK_START_IDX as usize] = block;
+
+        assert!(matches!(
+            Logger::open(&mut dev),
+            Err(Error::UnsupportedLogVersion { version: 0, .. })
+        ));
+        assert_eq!(dev.blocks_mut()[LOGGER_BLOCK_START_IDX as usize], block);
+    }
+
+    #[test]
+    fn open_continues_after_legacy_log() {
+        let mut dev = MemBlockDevice::new(1024);
+
+        // three blocks of bare entries and the settings pointing after them
+        let legacy_end = LOGGER_BLOCK_START_IDX + 3;
+        let mut block = [0u8; SD_BLOCK_SIZE];
+        postcard::to_slice(&entry(1), &mut block).unwrap();
+        for addr in LOGGER_BLOCK_START_IDX..legacy_end {
+            dev.blocks_mut()[addr as usize] = block;
+        }
+        let mut settings = [0u8; SD_BLOCK_SIZE];
+        postcard::to_slice(&legacy_end, &mut settings).unwrap();
+        dev.blocks_mut()[crate::storage::SETTINGS_BLOCK_IDX as usize] = settings;
+
+        let mut logger = Logger::open(&mut dev).unwrap();
+        assert_eq!(logger.next_block(), legacy_end + 1);
+        append_all(&mut logger, &mut dev, 3);
+        logger.flush(&mut dev).unwrap();
+
+        let header = LogHeader::parse(&dev.blocks_mut()[legacy_end as usize]).unwrap();
+        assert_eq!(header.seq_at(legacy_end + 1), 0);
+        let written = BlockHeader::parse(&dev.blocks_mut()[legacy_end as usize + 1]).unwrap();
+        assert_eq!(written.seq, 0);
+        for addr in LOGGER_BLOCK_START_IDX..legacy_end {
+            assert_eq!(dev.blocks_mut()[addr as usize], block);
+        }
+
+        // the log is still found once the settings are replaced
+        dev.blocks_mut()[crate::storage::SETTINGS_BLOCK_IDX as usize] = [0u8; SD_BLOCK_SIZE];
+        assert_eq!(
+            LegacyLog::find(&mut dev).unwrap().map(|l| l.end),
+            Some(legacy_end)
+        );
+        let mut logger = Logger::open(&mut dev).unwrap();
+        assert_eq!(logger.next_block(), legacy_end + 2);
+        append_all(&mut logger, &mut dev, 1);
+        logger.flush(&mut dev).unwrap();
+
+        // and starting over keeps the bare entries
+        let logger = Logger::create(&mut dev).unwrap();
+        assert_eq!(logger.next_block(), legacy_end + 1);
+        assert_eq!(logger.header().seq_at(legacy_end + 1), 2);
+        assert_eq!(dev.blocks_mut()[LOGGER_BLOCK_START_IDX as usize], block);
+    }
+}
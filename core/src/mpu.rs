@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+use crate::vec::Vec3f;
+
+/// Aggregated MPU6050 readings over a number of samples, as stored in the log.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Measurement {
+    pub acc_mean: Vec3f,
+    pub temp_mean: f32,
+    pub gyro_mag_max: f32,
+}
@@ -0,0 +1,1171 @@
+use core::fmt::{self, Write};
+
+use heapless::Vec;
+
+use crate::display::Display;
+use crate::SharedState;
+
+pub mod field;
+
+/// The kinds of panels, each registered at most once with the `PanelManager`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Panels {
+    Time,
+    Alarm,
+    Stats,
+    Sensor,
+    Settings,
+    Diagnostics,
+}
+
+impl Panels {
+    /// Name shown in the page indicator.
+    pub fn title(self) -> &'static str {
+        match self {
+            Panels::Time => "TIME",
+            Panels::Alarm => "ALARM",
+            Panels::Stats => "STATS",
+            Panels::Sensor => "SENSOR",
+            Panels::Settings => "SETTINGS",
+            Panels::Diagnostics => "DIAGNOSTICS",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CursorState {
+    Off,
+    Underline(u8, u8),
+    Blinking(u8, u8),
+}
+pub trait Panel<D: Display> {
+    fn next(&mut self, state: &mut SharedState);
+    fn previous(&mut self, state: &mut SharedState);
+    fn enter(&mut self, state: &mut SharedState);
+    fn leave(&mut self, state: &mut SharedState);
+    fn display(&self, disp: &mut D, state: &mut SharedState) -> Result<(), D::Error>;
+    fn get_cursor_state(&self, state: &SharedState) -> CursorState;
+    fn is_editing(&self) -> bool;
+
+    /// Returns `true` if the first field is selected, or if there are no fields to select.
+    fn at_first(&self) -> bool {
+        true
+    }
+
+    /// Returns `true` if the last field is selected, or if there are no fields to select.
+    fn at_last(&self) -> bool {
+        true
+    }
+
+    /// Selects the first field, when the panel is entered from the previous one.
+    fn select_first(&mut self) {}
+
+    /// Selects the last field, when the panel is entered from the next one.
+    fn select_last(&mut self) {}
+}
+
+/// Row of the page indicator, the panels managed by a `PanelManager` have the rows above it.
+const PAGE_ROW: u8 = 3;
+
+/// Owns up to `N` panels and forwards to the active one, showing its title and page number on
+/// the last row. Turning the encoder past the last or first field of a panel, while not editing,
+/// switches to the next or previous panel.
+pub struct PanelManager<'a, D: Display, const N: usize> {
+    panels: Vec<(Panels, &'a mut dyn Panel<D>), N>,
+    active: usize,
+}
+
+impl<'a, D: Display, const N: usize> PanelManager<'a, D, N> {
+    pub fn new() -> Self {
+        PanelManager {
+            panels: Vec::new(),
+            active: 0,
+        }
+    }
+
+    /// Adds a panel after the ones registered before it. Returns the kind of the panel back if
+    /// there is no room for it or it is already registered.
+    pub fn register(&mut self, kind: Panels, panel: &'a mut dyn Panel<D>) -> Result<(), Panels> {
+        if self.panels.iter().any(|(k, _)| *k == kind) {
+            return Err(kind);
+        }
+        self.panels.push((kind, panel)).map_err(|(k, _)| k)
+    }
+
+    /// The kind of the panel that is shown, or `None` if no panel is registered.
+    pub fn active(&self) -> Option<Panels> {
+        self.panels.get(self.active).map(|(k, _)| *k)
+    }
+
+    fn panel(&self) -> Option<&dyn Panel<D>> {
+        self.panels.get(self.active).map(|(_, p)| &**p)
+    }
+
+    fn panel_mut(&mut self) -> Option<&mut (dyn Panel<D> + 'a)> {
+        self.panels.get_mut(self.active).map(|(_, p)| &mut **p)
+    }
+}
+
+impl<'a, D: Display, const N: usize> Default for PanelManager<'a, D, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, D: Display, const N: usize> Panel<D> for PanelManager<'a, D, N> {
+    fn next(&mut self, state: &mut SharedState) {
+        let count = self.panels.len();
+        if let Some(panel) = self.panel_mut() {
+            if count > 1 && !panel.is_editing() && panel.at_last() {
+                self.active = (self.active + 1) % count;
+                self.panels[self.active].1.select_first();
+            } else {
+                panel.next(state);
+            }
+        }
+    }
+
+    fn previous(&mut self, state: &mut SharedState) {
+        let count = self.panels.len();
+        if let Some(panel) = self.panel_mut() {
+            if count > 1 && !panel.is_editing() && panel.at_first() {
+                self.active = (self.active + count - 1) % count;
+                self.panels[self.active].1.select_last();
+            } else {
+                panel.previous(state);
+            }
+        }
+    }
+
+    fn enter(&mut self, state: &mut SharedState) {
+        if let Some(panel) = self.panel_mut() {
+            panel.enter(state);
+        }
+    }
+
+    fn leave(&mut self, state: &mut SharedState) {
+        if let Some(panel) = self.panel_mut() {
+            panel.leave(state);
+        }
+    }
+
+    fn display(&self, disp: &mut D, state: &mut SharedState) -> Result<(), D::Error> {
+        let kind = match self.active() {
+            Some(kind) => kind,
+            None => return Ok(()),
+        };
+
+        self.panels[self.active].1.display(disp, state)?;
+
+        disp.set_cursor_position(PAGE_ROW, 0)?;
+        disp.write(kind.title().as_bytes())?;
+
+        // the page number, like "2/6", right-aligned
+        let mut page = TextBuf::new();
+        let _ = write!(page, "{}/{}", self.active + 1, self.panels.len());
+        disp.set_cursor_position(PAGE_ROW, 20 - page.as_bytes().len() as u8)?;
+        disp.write(page.as_bytes())
+    }
+
+    fn get_cursor_state(&self, state: &SharedState) -> CursorState {
+        self.panel()
+            .map_or(CursorState::Off, |p| p.get_cursor_state(state))
+    }
+
+    fn is_editing(&self) -> bool {
+        self.panel().is_some_and(|p| p.is_editing())
+    }
+}
+
+pub mod time {
+    use super::field::{Date, Field, Kind, Time};
+
+    /// The date and time of the clock.
+    pub const FIELDS: &[Field] = &[
+        Field {
+            label: "Time",
+            row: 0,
+            col: 7,
+            width: 8,
+            kind: Kind::Time(Time {
+                get: |s| [s.clock.hour, s.clock.minute, s.clock.second],
+                set: |s, [hour, minute, second]| {
+                    s.clock.hour = hour;
+                    s.clock.minute = minute;
+                    s.clock.second = second;
+                },
+                seconds: true,
+            }),
+        },
+        Field {
+            label: "Date",
+            row: 1,
+            col: 5,
+            width: 15,
+            kind: Kind::Date(Date {
+                get: |s| [s.clock.day, s.clock.month, s.clock.year],
+                set: |s, [day, month, year]| s.clock.set_date(day, month, year),
+            }),
+        },
+    ];
+}
+
+pub mod alarm {
+    use super::field::{Days, Field, Format, Kind, List, Number, Time, Toggle};
+    use crate::{
+        alarm::{ALARM_COUNT, MAX_VOLUME},
+        settings::AlarmTone,
+    };
+
+    /// One row per alarm, in the order of their numbers, with its time, its days of the week, its
+    /// wake window in minutes and if it is enabled, like "06:30 MTWTF-- 30  ON". Below them a row
+    /// per alarm with its tone and volume, like "Tone 1   ELISE   80%".
+    pub const FIELDS: &[Field] = &[
+        time::<0>(),
+        days::<0>(),
+        window::<0>(),
+        enabled::<0>(),
+        time::<1>(),
+        days::<1>(),
+        window::<1>(),
+        enabled::<1>(),
+        time::<2>(),
+        days::<2>(),
+        window::<2>(),
+        enabled::<2>(),
+        time::<3>(),
+        days::<3>(),
+        window::<3>(),
+        enabled::<3>(),
+        tone::<0>(),
+        volume::<0>(),
+        tone::<1>(),
+        volume::<1>(),
+        tone::<2>(),
+        volume::<2>(),
+        tone::<3>(),
+        volume::<3>(),
+    ];
+
+    const TONE_LABELS: [&str; ALARM_COUNT] = ["Tone 1", "Tone 2", "Tone 3", "Tone 4"];
+
+    /// Names of the `AlarmTone`s, in the order of `AlarmTone::ALL`.
+    const TONE_NAMES: &[&str] = &["BEEP", "CHIRP", "SIREN", "ODE", "ELISE", "MORNING"];
+
+    const fn time<const I: usize>() -> Field {
+        Field {
+            label: "",
+            row: I as u8,
+            col: 0,
+            width: 5,
+            kind: Kind::Time(Time {
+                get: |s| [s.settings.alarms[I].hour, s.settings.alarms[I].minute, 0],
+                set: |s, [hour, minute, _]| {
+                    s.settings.alarms[I].hour = hour;
+                    s.settings.alarms[I].minute = minute;
+                },
+                seconds: false,
+            }),
+        }
+    }
+
+    const fn days<const I: usize>() -> Field {
+        Field {
+            label: "",
+            row: I as u8,
+            col: 6,
+            width: 7,
+            kind: Kind::Days(Days {
+                get: |s| s.settings.alarms[I].days,
+                set: |s, days| s.settings.alarms[I].days = days,
+            }),
+        }
+    }
+
+    const fn window<const I: usize>() -> Field {
+        Field {
+            label: "",
+            row: I as u8,
+            col: 14,
+            width: 2,
+            // no window is shown as "--", one step below the shortest one
+            kind: Kind::Number(Number {
+                get: |s| match s.settings.alarms[I].window {
+                    0 => None,
+                    w => Some(w.into()),
+                },
+                set: |s, v| s.settings.alarms[I].window = v.unwrap_or(0) as u8,
+                min: 5,
+                max: 60,
+                step: 5,
+                wrap: false,
+                none: Some("--"),
+                format: Format::Digits(2),
+            }),
+        }
+    }
+
+    const fn enabled<const I: usize>() -> Field {
+        Field {
+            label: "",
+            row: I as u8,
+            col: 17,
+            width: 3,
+            kind: Kind::Toggle(Toggle {
+                get: |s| s.settings.alarms[I].enabled,
+                set: |s, on| s.settings.alarms[I].enabled = on,
+            }),
+        }
+    }
+
+    const fn tone<const I: usize>() -> Field {
+        Field {
+            label: TONE_LABELS[I],
+            row: (ALARM_COUNT + I) as u8,
+            col: 7,
+            width: 7,
+            kind: Kind::List(List {
+                get: |s| s.settings.alarms[I].tone as usize,
+                set: |s, i| s.settings.alarms[I].tone = AlarmTone::ALL[i],
+                names: TONE_NAMES,
+            }),
+        }
+    }
+
+    const fn volume<const I: usize>() -> Field {
+        Field {
+            label: "",
+            row: (ALARM_COUNT + I) as u8,
+            col: 16,
+            width: 4,
+            kind: Kind::Number(Number {
+                get: |s| Some(s.settings.alarms[I].volume.into()),
+                set: |s, v| s.settings.alarms[I].volume = v.unwrap_or(MAX_VOLUME.into()) as u8,
+                min: 10,
+                max: MAX_VOLUME as u16,
+                step: 10,
+                wrap: false,
+                none: None,
+                format: Format::Percent,
+            }),
+        }
+    }
+}
+
+pub mod stats {
+    use core::fmt::Write;
+
+    use super::{write_row, CursorState, TextBuf};
+    use crate::app::POLL_FREQ;
+    use crate::display::Display;
+    use crate::SharedState;
+
+    /// Shows the counters kept in `SharedState::stats` since boot.
+    pub struct StatsPanel {}
+
+    impl StatsPanel {
+        pub fn new() -> Self {
+            StatsPanel {}
+        }
+    }
+
+    impl Default for StatsPanel {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<D: Display> crate::panel::Panel<D> for StatsPanel {
+        fn enter(&mut self, _state: &mut SharedState) {}
+
+        fn leave(&mut self, _state: &mut SharedState) {}
+
+        fn next(&mut self, _state: &mut SharedState) {}
+
+        fn previous(&mut self, _state: &mut SharedState) {}
+
+        fn display(&self, disp: &mut D, state: &mut SharedState) -> Result<(), D::Error> {
+            let stats = &state.stats;
+
+            let mut text = TextBuf::new();
+            let seconds = stats.ticks / POLL_FREQ;
+            let _ = write!(
+                text,
+                "{}d {:02}:{:02}:{:02}",
+                seconds / 86400,
+                seconds / 3600 % 24,
+                seconds / 60 % 60,
+                seconds % 60
+            );
+            write_row(disp, 0, "Uptime", &text)?;
+
+            let mut text = TextBuf::new();
+            let _ = write!(text, "{}", stats.measurements);
+            write_row(disp, 1, "Measurements", &text)?;
+
+            let mut text = TextBuf::new();
+            let _ = match stats.temp_range {
+                Some((min, max)) => write!(text, "{:.1}-{:.1}C", min, max),
+                None => write!(text, "-"),
+            };
+            write_row(disp, 2, "Temp", &text)
+        }
+
+        fn get_cursor_state(&self, _state: &SharedState) -> CursorState {
+            CursorState::Off
+        }
+
+        fn is_editing(&self) -> bool {
+            false
+        }
+    }
+}
+
+pub mod sensor {
+    use core::fmt::Write;
+
+    use super::{write_row, CursorState, TextBuf};
+    use crate::display::Display;
+    use crate::SharedState;
+
+    /// Shows the last measurement of the MPU.
+    pub struct SensorPanel {}
+
+    impl SensorPanel {
+        pub fn new() -> Self {
+            SensorPanel {}
+        }
+    }
+
+    impl Default for SensorPanel {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<D: Display> crate::panel::Panel<D> for SensorPanel {
+        fn enter(&mut self, _state: &mut SharedState) {}
+
+        fn leave(&mut self, _state: &mut SharedState) {}
+
+        fn next(&mut self, _state: &mut SharedState) {}
+
+        fn previous(&mut self, _state: &mut SharedState) {}
+
+        fn display(&self, disp: &mut D, state: &mut SharedState) -> Result<(), D::Error> {
+            let m = match &state.measurement {
+                Some(m) => m,
+                None => {
+                    disp.set_cursor_position(0, 0)?;
+                    return disp.write(b"Waiting for sensor");
+                }
+            };
+
+            let mut text = TextBuf::new();
+            let _ = write!(text, "{:.1}C", m.temp_mean);
+            write_row(disp, 0, "Temp", &text)?;
+
+            let mut text = TextBuf::new();
+            let acc = m.acc_mean;
+            let _ = write!(text, "{:+5.2}{:+5.2}{:+5.2}", acc.0, acc.1, acc.2);
+            write_row(disp, 1, "Acc", &text)?;
+
+            let mut text = TextBuf::new();
+            let _ = write!(text, "{:.2}", m.gyro_mag_max);
+            write_row(disp, 2, "Gyro max", &text)
+        }
+
+        fn get_cursor_state(&self, _state: &SharedState) -> CursorState {
+            CursorState::Off
+        }
+
+        fn is_editing(&self) -> bool {
+            false
+        }
+    }
+}
+
+pub mod settings {
+    use super::field::{Field, Format, Kind, Number};
+
+    /// The user settings. The changes are made to `SharedState::settings` right away, so the main
+    /// loop can apply them while they are being edited.
+    pub const FIELDS: &[Field] = &[
+        Field {
+            label: "Brightness",
+            row: 0,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| Some(s.settings.backlight_brightness.into()),
+                set: |s, v| s.settings.backlight_brightness = v.unwrap_or(0) as u8,
+                min: 0,
+                max: 255,
+                step: 8,
+                wrap: false,
+                none: None,
+                format: Format::Digits(3),
+            }),
+        },
+        Field {
+            label: "Contrast",
+            row: 1,
+            col: 15,
+            width: 5,
+            // stepping down from the lowest contrast goes back to the one of the display
+            kind: Kind::Number(Number {
+                get: |s| s.settings.contrast.map(u16::from),
+                set: |s, v| s.settings.contrast = v.map(|v| v as u8),
+                min: 0,
+                max: 255,
+                step: 8,
+                wrap: false,
+                none: Some("KEEP"),
+                format: Format::Digits(3),
+            }),
+        },
+        Field {
+            label: "Log samples",
+            row: 2,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| Some(s.settings.mpu_samples),
+                set: |s, v| s.settings.mpu_samples = v.unwrap_or(1),
+                min: 1,
+                max: 600,
+                step: 1,
+                wrap: false,
+                none: None,
+                format: Format::Digits(3),
+            }),
+        },
+        Field {
+            label: "Long press",
+            row: 3,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| Some(s.settings.long_press_duration),
+                set: |s, v| s.settings.long_press_duration = v.unwrap_or(500),
+                min: 500,
+                max: 5000,
+                step: 100,
+                wrap: false,
+                none: None,
+                format: Format::Seconds,
+            }),
+        },
+        Field {
+            label: "Snooze minutes",
+            row: 4,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| Some(s.settings.snooze_duration as u16),
+                set: |s, v| s.settings.snooze_duration = v.unwrap_or(9) as u8,
+                min: 1,
+                max: 30,
+                step: 1,
+                wrap: false,
+                none: None,
+                format: Format::Digits(2),
+            }),
+        },
+        Field {
+            label: "Sunrise minutes",
+            row: 5,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| match s.settings.sunrise_duration {
+                    0 => None,
+                    d => Some(d.into()),
+                },
+                set: |s, v| s.settings.sunrise_duration = v.unwrap_or(0) as u8,
+                min: 5,
+                max: 60,
+                step: 5,
+                wrap: false,
+                none: Some("OFF"),
+                format: Format::Digits(2),
+            }),
+        },
+        Field {
+            label: "Night bright",
+            row: 6,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| Some(s.settings.night_brightness.into()),
+                set: |s, v| s.settings.night_brightness = v.unwrap_or(0) as u8,
+                min: 0,
+                max: 255,
+                step: 8,
+                wrap: false,
+                none: None,
+                format: Format::Digits(3),
+            }),
+        },
+        Field {
+            label: "Night from",
+            row: 7,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| Some(s.settings.night_start.into()),
+                set: |s, v| s.settings.night_start = v.unwrap_or(0) as u8,
+                min: 0,
+                max: 23,
+                step: 1,
+                wrap: true,
+                none: None,
+                format: Format::Digits(2),
+            }),
+        },
+        Field {
+            label: "Night until",
+            row: 8,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| Some(s.settings.night_end.into()),
+                set: |s, v| s.settings.night_end = v.unwrap_or(0) as u8,
+                min: 0,
+                max: 23,
+                step: 1,
+                wrap: true,
+                none: None,
+                format: Format::Digits(2),
+            }),
+        },
+        Field {
+            label: "Dim after secs",
+            row: 9,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| match s.settings.backlight_timeout {
+                    0 => None,
+                    t => Some(t),
+                },
+                set: |s, v| s.settings.backlight_timeout = v.unwrap_or(0),
+                min: 10,
+                max: 600,
+                step: 10,
+                wrap: false,
+                none: Some("NEVER"),
+                format: Format::Digits(3),
+            }),
+        },
+        Field {
+            label: "Dimmed bright",
+            row: 10,
+            col: 15,
+            width: 5,
+            kind: Kind::Number(Number {
+                get: |s| Some(s.settings.idle_brightness.into()),
+                set: |s, v| s.settings.idle_brightness = v.unwrap_or(0) as u8,
+                min: 0,
+                max: 255,
+                step: 8,
+                wrap: false,
+                none: None,
+                format: Format::Digits(3),
+            }),
+        },
+    ];
+}
+
+pub mod diagnostics {
+    use core::fmt::Write;
+
+    use super::{write_row, CursorState, TextBuf};
+    use crate::display::Display;
+    use crate::storage::{ErrorKind, SettingsSource};
+    use crate::SharedState;
+
+    /// Shows the state of the log and the settings on the SD-card, and the firmware version.
+    pub struct DiagnosticsPanel {}
+
+    impl DiagnosticsPanel {
+        pub fn new() -> Self {
+            DiagnosticsPanel {}
+        }
+    }
+
+    impl Default for DiagnosticsPanel {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<D: Display> crate::panel::Panel<D> for DiagnosticsPanel {
+        fn enter(&mut self, _state: &mut SharedState) {}
+
+        fn leave(&mut self, _state: &mut SharedState) {}
+
+        fn next(&mut self, _state: &mut SharedState) {}
+
+        fn previous(&mut self, _state: &mut SharedState) {}
+
+        fn display(&self, disp: &mut D, state: &mut SharedState) -> Result<(), D::Error> {
+            let diag = &state.diagnostics;
+
+            let mut text = TextBuf::new();
+            let _ = match (diag.log_block, diag.log_error) {
+                (Some(block), _) => write!(text, "block {}", block),
+                (None, Some(ErrorKind::Device)) => write!(text, "CARD ERROR"),
+                (None, Some(ErrorKind::Encoding)) => write!(text, "DATA ERROR"),
+                (None, Some(ErrorKind::DeviceFull)) => write!(text, "CARD FULL"),
+                (None, Some(ErrorKind::UnsupportedLogVersion { version })) => {
+                    write!(text, "FORMAT v{}", version)
+                }
+                (None, None) => write!(text, "OFF"),
+            };
+            write_row(disp, 0, "Log", &text)?;
+
+            let mut text = TextBuf::new();
+            let _ = match diag.settings_source {
+                SettingsSource::Stored { block, seq } => write!(text, "block {} #{}", block, seq),
+                SettingsSource::Legacy => write!(text, "LEGACY"),
+                SettingsSource::Default => write!(text, "DEFAULT"),
+            };
+            write_row(disp, 1, "Settings", &text)?;
+
+            let mut text = TextBuf::new();
+            let _ = write!(text, "{}", env!("CARGO_PKG_VERSION"));
+            write_row(disp, 2, "Version", &text)
+        }
+
+        fn get_cursor_state(&self, _state: &SharedState) -> CursorState {
+            CursorState::Off
+        }
+
+        fn is_editing(&self) -> bool {
+            false
+        }
+    }
+}
+
+/// Text formatted with `write!`, cut off at the width of the display.
+struct TextBuf {
+    buf: [u8; 20],
+    len: usize,
+}
+
+impl TextBuf {
+    fn new() -> Self {
+        TextBuf {
+            buf: [b' '; 20],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for TextBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// Writes the label at the start of the row and the value right-aligned at its end.
+fn write_row<D: Display>(
+    disp: &mut D,
+    row: u8,
+    label: &str,
+    value: &TextBuf,
+) -> Result<(), D::Error> {
+    disp.set_cursor_position(row, 0)?;
+    disp.write(label.as_bytes())?;
+    disp.set_cursor_position(row, 20 - value.len as u8)?;
+    disp.write(value.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diagnostics::DiagnosticsPanel;
+    use super::field::FieldPanel;
+    use super::sensor::SensorPanel;
+    use super::stats::StatsPanel;
+    use super::*;
+    use crate::clock::ClockState;
+    use crate::display::BufferedDisplay;
+    use crate::mpu::Measurement;
+    use crate::settings::AlarmTone;
+    use crate::settings::Settings;
+    use crate::storage::{ErrorKind, SettingsSource};
+    use crate::vec::Vec3f;
+    use crate::{Diagnostics, Stats};
+
+    type Disp = BufferedDisplay<4, 20>;
+
+    fn state() -> SharedState {
+        SharedState {
+            clock: ClockState {
+                hour: 23,
+                minute: 59,
+                second: 7,
+                weekday: 1,
+                day: 20,
+                month: 9,
+                year: 21,
+            },
+            settings: Settings::default(),
+            measurement: None,
+            stats: Stats::default(),
+            diagnostics: Diagnostics::default(),
+        }
+    }
+
+    #[test]
+    fn time_panel_renders_state() {
+        let panel = FieldPanel::new(time::FIELDS);
+        let mut disp = Disp::new();
+        Panel::<Disp>::display(&panel, &mut disp, &mut state()).unwrap();
+
+        assert_eq!(&disp.rows()[0], b"Time   23:59:07     ");
+        assert_eq!(&disp.rows()[1], b"Date MON 20 SEP 2021");
+    }
+
+    #[test]
+    fn time_panel_selects_and_edits_fields() {
+        let mut panel = FieldPanel::new(time::FIELDS);
+        let mut s = state();
+
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(0, 8));
+
+        // move to the minute field and enter edit mode
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        assert!(Panel::<Disp>::is_editing(&panel));
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Blinking(0, 11));
+
+        Panel::<Disp>::next(&mut panel, &mut s);
+        assert_eq!(s.clock.minute, 0);
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert_eq!(s.clock.minute, 58);
+
+        Panel::<Disp>::leave(&mut panel, &mut s);
+        assert!(!Panel::<Disp>::is_editing(&panel));
+
+        // the weekday follows the date
+        for _ in 0..3 {
+            Panel::<Disp>::next(&mut panel, &mut s);
+        }
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::leave(&mut panel, &mut s);
+        assert_eq!((s.clock.day, s.clock.month, s.clock.weekday), (20, 10, 3));
+        for _ in 0..3 {
+            Panel::<Disp>::previous(&mut panel, &mut s);
+        }
+
+        // selection wraps around from the first to the last field
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert!(Panel::<Disp>::at_first(&panel));
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert!(Panel::<Disp>::at_last(&panel));
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(1, 19));
+    }
+
+    #[test]
+    fn alarm_panel_edits_alarms() {
+        let mut panel = FieldPanel::new(alarm::FIELDS);
+        let mut s = state();
+        s.settings.alarms[0].enabled = true;
+        let mut disp = Disp::new();
+
+        Panel::<Disp>::display(&panel, &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"06:30 MTWTF-- --  ON");
+        assert_eq!(&disp.rows()[1], b"09:00 -----SS -- OFF");
+        assert_eq!(&disp.rows()[2], b"07:00 MTWTFSS -- OFF");
+
+        // the tones and volumes follow the alarms
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(2, 19));
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        Panel::<Disp>::leave(&mut panel, &mut s);
+        assert_eq!(s.settings.alarms[3].volume, 90);
+
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(2, 13));
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        Panel::<Disp>::leave(&mut panel, &mut s);
+        assert_eq!(s.settings.alarms[3].tone, AlarmTone::MorningMood);
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&panel, &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[1], b"Tone 3    BEEP  100%");
+        assert_eq!(&disp.rows()[2], b"Tone 4 MORNING   90%");
+
+        // the last alarm is scrolled into view
+        for _ in 0..7 {
+            Panel::<Disp>::previous(&mut panel, &mut s);
+        }
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(2, 19));
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::leave(&mut panel, &mut s);
+        assert!(s.settings.alarms[3].enabled);
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&panel, &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[2], b"07:00 MTWTFSS --  ON");
+
+        // the days follow the minute
+        Panel::<Disp>::select_first(&mut panel);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(0, 6));
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::leave(&mut panel, &mut s);
+        assert!(!s.settings.alarms[0].days.contains(1));
+
+        // and the wake window the days, starting at the shortest one
+        for _ in 0..7 {
+            Panel::<Disp>::next(&mut panel, &mut s);
+        }
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(0, 15));
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::leave(&mut panel, &mut s);
+        assert_eq!(s.settings.alarms[0].window, 10);
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&panel, &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"06:30 -TWTF-- 10  ON");
+
+        Panel::<Disp>::select_first(&mut panel);
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert_eq!(s.settings.alarms[0].hour, 5);
+    }
+
+    #[test]
+    fn info_panels_render_state() {
+        let mut s = state();
+        s.stats = Stats {
+            ticks: (86400 + 3600 + 2 * 60 + 3) * crate::app::POLL_FREQ,
+            ..Stats::default()
+        };
+        let m = Measurement {
+            acc_mean: Vec3f(0.01, -0.02, 0.98),
+            temp_mean: 21.34,
+            gyro_mag_max: 1.5,
+        };
+        s.stats.record(&Measurement {
+            temp_mean: 23.0,
+            ..m.clone()
+        });
+        s.stats.record(&m);
+        s.diagnostics = Diagnostics {
+            log_block: Some(1234),
+            log_error: None,
+            settings_source: SettingsSource::Stored { block: 2, seq: 7 },
+        };
+
+        let mut disp = Disp::new();
+        Panel::<Disp>::display(&SensorPanel::new(), &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"Waiting for sensor  ");
+
+        s.measurement = Some(m);
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&SensorPanel::new(), &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"Temp           21.3C");
+        assert_eq!(&disp.rows()[1], b"Acc  +0.01-0.02+0.98");
+        assert_eq!(&disp.rows()[2], b"Gyro max        1.50");
+
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&StatsPanel::new(), &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"Uptime   1d 01:02:03");
+        assert_eq!(&disp.rows()[1], b"Measurements       2");
+        assert_eq!(&disp.rows()[2], b"Temp      21.3-23.0C");
+
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&DiagnosticsPanel::new(), &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"Log       block 1234");
+        assert_eq!(&disp.rows()[1], b"Settings  block 2 #7");
+        assert_eq!(&disp.rows()[2], b"Version        0.1.0");
+
+        // the reason logging stopped
+        s.diagnostics.log_block = None;
+        s.diagnostics.log_error = Some(ErrorKind::DeviceFull);
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&DiagnosticsPanel::new(), &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"Log        CARD FULL");
+    }
+
+    #[test]
+    fn manager_switches_panels_at_the_ends() {
+        let mut time = FieldPanel::new(time::FIELDS);
+        let mut alarm = FieldPanel::new(alarm::FIELDS);
+        let mut stats = StatsPanel::new();
+        let mut manager: PanelManager<Disp, 3> = PanelManager::new();
+        manager.register(Panels::Time, &mut time).unwrap();
+        manager.register(Panels::Alarm, &mut alarm).unwrap();
+        manager.register(Panels::Stats, &mut stats).unwrap();
+
+        let mut s = state();
+        let mut disp = Disp::new();
+
+        manager.display(&mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[3], b"TIME             1/3");
+
+        // the year is the last field of the time panel
+        for _ in 0..6 {
+            manager.next(&mut s);
+        }
+        assert_eq!(manager.active(), Some(Panels::Alarm));
+        assert!(manager.get_cursor_state(&s) == CursorState::Underline(0, 1));
+
+        // back to the last field of the time panel
+        manager.previous(&mut s);
+        assert_eq!(manager.active(), Some(Panels::Time));
+        assert!(manager.get_cursor_state(&s) == CursorState::Underline(1, 19));
+
+        // the first panel wraps around to the last one, and panels without fields are switched
+        // right away
+        for _ in 0..6 {
+            manager.previous(&mut s);
+        }
+        assert_eq!(manager.active(), Some(Panels::Stats));
+        assert!(manager.get_cursor_state(&s) == CursorState::Off);
+        disp.clear().unwrap();
+        manager.display(&mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[3], b"STATS            3/3");
+        manager.next(&mut s);
+        assert_eq!(manager.active(), Some(Panels::Time));
+    }
+
+    #[test]
+    fn manager_does_not_switch_while_editing() {
+        let mut time = FieldPanel::new(time::FIELDS);
+        let mut alarm = FieldPanel::new(alarm::FIELDS);
+        let mut manager: PanelManager<Disp, 2> = PanelManager::new();
+        manager.register(Panels::Time, &mut time).unwrap();
+        manager.register(Panels::Alarm, &mut alarm).unwrap();
+
+        let mut s = state();
+        manager.enter(&mut s);
+        assert!(manager.is_editing());
+        manager.previous(&mut s);
+        assert_eq!(manager.active(), Some(Panels::Time));
+        assert_eq!(s.clock.hour, 22);
+
+        manager.leave(&mut s);
+        manager.previous(&mut s);
+        assert_eq!(manager.active(), Some(Panels::Alarm));
+    }
+
+    #[test]
+    fn manager_registers_each_kind_once() {
+        let mut time = FieldPanel::new(time::FIELDS);
+        let mut other = FieldPanel::new(time::FIELDS);
+        let mut stats = StatsPanel::new();
+        let mut manager: PanelManager<Disp, 1> = PanelManager::new();
+
+        assert_eq!(manager.active(), None);
+        assert!(manager.get_cursor_state(&state()) == CursorState::Off);
+
+        manager.register(Panels::Time, &mut time).unwrap();
+        assert_eq!(
+            manager.register(Panels::Time, &mut other),
+            Err(Panels::Time)
+        );
+        assert_eq!(
+            manager.register(Panels::Stats, &mut stats),
+            Err(Panels::Stats)
+        );
+    }
+
+    #[test]
+    fn settings_panel_renders_settings() {
+        let panel = FieldPanel::new(super::settings::FIELDS);
+        let mut disp = Disp::new();
+        Panel::<Disp>::display(&panel, &mut disp, &mut state()).unwrap();
+
+        assert_eq!(&disp.rows()[0], b"Brightness       064");
+        assert_eq!(&disp.rows()[1], b"Contrast        KEEP");
+        assert_eq!(&disp.rows()[2], b"Log samples      010");
+        assert_eq!(&disp.rows()[3], b"                    ");
+    }
+
+    #[test]
+    fn settings_panel_scrolls_to_selected_field() {
+        let mut panel = FieldPanel::new(super::settings::FIELDS);
+        let mut s = state();
+        let mut disp = Disp::new();
+
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(2, 19));
+        Panel::<Disp>::display(&panel, &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"Night until       07");
+        assert_eq!(&disp.rows()[1], b"Dim after secs   060");
+        assert_eq!(&disp.rows()[2], b"Dimmed bright    008");
+
+        for _ in 0..5 {
+            Panel::<Disp>::previous(&mut panel, &mut s);
+        }
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&panel, &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"Long press      2.0s");
+        assert_eq!(&disp.rows()[1], b"Snooze minutes    09");
+        assert_eq!(&disp.rows()[2], b"Sunrise minutes  OFF");
+
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        assert_eq!(s.settings.sunrise_duration, 5);
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&panel, &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[2], b"Sunrise minutes   05");
+
+        // wraps around to the first field
+        Panel::<Disp>::leave(&mut panel, &mut s);
+        for _ in 0..6 {
+            Panel::<Disp>::next(&mut panel, &mut s);
+        }
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(0, 19));
+    }
+
+    #[test]
+    fn settings_panel_edits_settings() {
+        let mut panel = FieldPanel::new(super::settings::FIELDS);
+        let mut s = state();
+
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Blinking(0, 19));
+        Panel::<Disp>::next(&mut panel, &mut s);
+        assert_eq!(s.settings.backlight_brightness, 72);
+        Panel::<Disp>::enter(&mut panel, &mut s);
+
+        // the contrast of the display is kept until it is set
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert_eq!(s.settings.contrast, None);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        assert_eq!(s.settings.contrast, Some(8));
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert_eq!(s.settings.contrast, None);
+        Panel::<Disp>::leave(&mut panel, &mut s);
+
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert_eq!(s.settings.long_press_duration, 1900);
+        assert_eq!(s.settings.long_press_ticks(), 19);
+    }
+}
@@ -0,0 +1,595 @@
+//! Panels declared as a list of fields.
+//!
+//! Each `Field` declares where its value is shown, how it is formatted and how it is edited, so a
+//! `FieldPanel` can do the selection, editing, rendering and cursor placement for any list of
+//! them. Fields made of several parts, like a time, have each part selected on its own.
+use core::fmt::Write;
+
+use super::{CursorState, Panel, TextBuf, PAGE_ROW};
+use crate::alarm::Weekdays;
+use crate::clock::{days_in_month, weekday};
+use crate::display::Display;
+use crate::SharedState;
+
+const WEEKDAYS: [&str; 7] = ["MON", "TUE", "WED", "THU", "FRI", "SAT", "SUN"];
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+/// Number of rows above the page indicator. A panel with fields further down scrolls to keep the
+/// selected field visible.
+const ROWS: u8 = PAGE_ROW;
+
+/// A value shown at a fixed place of a panel and edited with the encoder.
+pub struct Field {
+    /// Written at the start of the row, unless empty.
+    pub label: &'static str,
+    pub row: u8,
+    /// First column of the value, which is right-aligned within `width` characters.
+    pub col: u8,
+    pub width: u8,
+    pub kind: Kind,
+}
+
+pub enum Kind {
+    Number(Number),
+    List(List),
+    Toggle(Toggle),
+    Time(Time),
+    Date(Date),
+    Days(Days),
+}
+
+/// A number between `min` and `max`, changed in steps.
+pub struct Number {
+    pub get: fn(&SharedState) -> Option<u16>,
+    pub set: fn(&mut SharedState, Option<u16>),
+    pub min: u16,
+    pub max: u16,
+    pub step: u16,
+    /// Wraps around at the ends of the range instead of stopping there.
+    pub wrap: bool,
+    /// If set, `None` is a valid value one step below `min`, shown as this text.
+    pub none: Option<&'static str>,
+    pub format: Format,
+}
+
+pub enum Format {
+    /// Zero-padded to the number of digits.
+    Digits(usize),
+    /// Milliseconds shown as seconds with one decimal, like "2.5s".
+    Seconds,
+    /// A percentage, like "80%".
+    Percent,
+}
+
+/// One of a list of names, stored as the index in the list. Wraps around at the ends.
+pub struct List {
+    pub get: fn(&SharedState) -> usize,
+    pub set: fn(&mut SharedState, usize),
+    pub names: &'static [&'static str],
+}
+
+/// On or off.
+pub struct Toggle {
+    pub get: fn(&SharedState) -> bool,
+    pub set: fn(&mut SharedState, bool),
+}
+
+/// Hour, minute and second, shown like "23:59:07". Without `seconds` the second is neither shown
+/// nor changed.
+pub struct Time {
+    pub get: fn(&SharedState) -> [u8; 3],
+    pub set: fn(&mut SharedState, [u8; 3]),
+    pub seconds: bool,
+}
+
+/// Day, month and year of the century, shown with the weekday like "MON 20 SEP 2021". The day
+/// is kept within the length of the month.
+pub struct Date {
+    pub get: fn(&SharedState) -> [u8; 3],
+    pub set: fn(&mut SharedState, [u8; 3]),
+}
+
+/// A set of weekdays, shown with the initial of each day in the set like "MTWTF--". Each day is
+/// selected on its own and toggled.
+pub struct Days {
+    pub get: fn(&SharedState) -> Weekdays,
+    pub set: fn(&mut SharedState, Weekdays),
+}
+
+impl Kind {
+    /// Number of parts that are selected on their own.
+    fn parts(&self) -> usize {
+        match self {
+            Kind::Time(t) if t.seconds => 3,
+            Kind::Time(_) => 2,
+            Kind::Date(_) => 3,
+            Kind::Days(_) => 7,
+            _ => 1,
+        }
+    }
+
+    /// Position of the last character of the part, counted from the end of the value.
+    fn cursor_from_end(&self, part: usize) -> u8 {
+        match self {
+            Kind::Time(_) => (self.parts() - 1 - part) as u8 * 3,
+            Kind::Date(_) => [9, 5, 0][part],
+            Kind::Days(_) => 6 - part as u8,
+            _ => 0,
+        }
+    }
+
+    fn step(&self, state: &mut SharedState, part: usize, forward: bool) {
+        match self {
+            Kind::Number(n) => {
+                let value = match ((n.get)(state), forward) {
+                    (None, true) => Some(n.min),
+                    (None, false) => None,
+                    (Some(v), false) if v <= n.min && n.none.is_some() => None,
+                    (Some(v), _) => Some(step(v, n.min, n.max, n.step, n.wrap, forward)),
+                };
+                (n.set)(state, value);
+            }
+            Kind::List(l) => {
+                let last = l.names.len() - 1;
+                let i = (l.get)(state).min(last);
+                (l.set)(state, step(i, 0, last, 1, true, forward));
+            }
+            Kind::Toggle(t) => {
+                let on = (t.get)(state);
+                (t.set)(state, !on);
+            }
+            Kind::Time(t) => {
+                let mut time = (t.get)(state);
+                let max = [23, 59, 59][part];
+                time[part] = step(time[part], 0, max, 1, true, forward);
+                (t.set)(state, time);
+            }
+            Kind::Date(d) => {
+                let [day, month, year] = (d.get)(state);
+                let [day, month, year] = match part {
+                    0 => [
+                        step(day, 1, days_in_month(month, year), 1, true, forward),
+                        month,
+                        year,
+                    ],
+                    1 => [day, step(month, 1, 12, 1, true, forward), year],
+                    _ => [day, month, step(year, 0, 99, 1, true, forward)],
+                };
+                (d.set)(state, [day.min(days_in_month(month, year)), month, year]);
+            }
+            Kind::Days(d) => {
+                let mut days = (d.get)(state);
+                days.toggle(part as u8 + 1);
+                (d.set)(state, days);
+            }
+        }
+    }
+
+    fn write(&self, state: &SharedState, text: &mut TextBuf) -> core::fmt::Result {
+        match self {
+            Kind::Number(n) => match ((n.get)(state), &n.format) {
+                (None, _) => text.write_str(n.none.unwrap_or("-")),
+                (Some(v), Format::Digits(width)) => write!(text, "{:01$}", v, width),
+                (Some(v), Format::Seconds) => write!(text, "{}.{}s", v / 1000, v / 100 % 10),
+                (Some(v), Format::Percent) => write!(text, "{}%", v),
+            },
+            Kind::List(l) => text.write_str(l.names.get((l.get)(state)).unwrap_or(&"?")),
+            Kind::Toggle(t) => text.write_str(if (t.get)(state) { "ON" } else { "OFF" }),
+            Kind::Time(t) => {
+                let [hour, minute, second] = (t.get)(state);
+                write!(text, "{:02}:{:02}", hour, minute)?;
+                if t.seconds {
+                    write!(text, ":{:02}", second)?;
+                }
+                Ok(())
+            }
+            Kind::Date(d) => {
+                let [day, month, year] = (d.get)(state);
+                let valid = (1..=12).contains(&month)
+                    && (1..=days_in_month(month, year)).contains(&day)
+                    && year <= 99;
+                let (weekday, month) = match valid {
+                    true => (
+                        WEEKDAYS[weekday(day, month, year) as usize - 1],
+                        MONTHS[month as usize - 1],
+                    ),
+                    false => ("???", "???"),
+                };
+                write!(text, "{} {:02} {} 20{:02}", weekday, day, month, year)
+            }
+            Kind::Days(d) => {
+                let days = (d.get)(state);
+                for (i, name) in WEEKDAYS.iter().enumerate() {
+                    let c = match days.contains(i as u8 + 1) {
+                        true => name.as_bytes()[0] as char,
+                        false => '-',
+                    };
+                    text.write_char(c)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Steps `value` within `min..=max`, stopping at or wrapping around the ends. Values outside of
+/// the range are brought back into it.
+fn step<T>(value: T, min: T, max: T, step: T, wrap: bool, forward: bool) -> T
+where
+    T: Copy + PartialOrd + core::ops::Add<Output = T> + core::ops::Sub<Output = T>,
+{
+    if value < min {
+        min
+    } else if value > max {
+        max
+    } else if forward {
+        match max - value < step {
+            true if wrap && value == max => min,
+            true => max,
+            false => value + step,
+        }
+    } else {
+        match value - min < step {
+            true if wrap && value == min => max,
+            true => min,
+            false => value - step,
+        }
+    }
+}
+
+/// A panel made of a list of fields, which must not be empty.
+pub struct FieldPanel {
+    fields: &'static [Field],
+    /// Index of the selected field and of the selected part of it.
+    selected: (usize, usize),
+    in_edit: bool,
+}
+
+impl FieldPanel {
+    pub fn new(fields: &'static [Field]) -> Self {
+        assert!(!fields.is_empty(), "a FieldPanel needs at least one field");
+        FieldPanel {
+            fields,
+            selected: (0, 0),
+            in_edit: false,
+        }
+    }
+
+    fn field(&self) -> &'static Field {
+        &self.fields[self.selected.0]
+    }
+
+    /// Row shown at the top of the display.
+    fn first_row(&self) -> u8 {
+        self.field().row.saturating_sub(ROWS - 1)
+    }
+}
+
+impl<D: Display> Panel<D> for FieldPanel {
+    fn enter(&mut self, _state: &mut SharedState) {
+        self.in_edit = !self.in_edit;
+    }
+
+    fn leave(&mut self, _state: &mut SharedState) {
+        self.in_edit = false;
+    }
+
+    fn next(&mut self, state: &mut SharedState) {
+        let (field, part) = self.selected;
+        if self.in_edit {
+            self.field().kind.step(state, part, true);
+        } else if part + 1 < self.field().kind.parts() {
+            self.selected = (field, part + 1);
+        } else {
+            self.selected = ((field + 1) % self.fields.len(), 0);
+        }
+    }
+
+    fn previous(&mut self, state: &mut SharedState) {
+        let (field, part) = self.selected;
+        if self.in_edit {
+            self.field().kind.step(state, part, false);
+        } else if part > 0 {
+            self.selected = (field, part - 1);
+        } else {
+            let field = (field + self.fields.len() - 1) % self.fields.len();
+            self.selected = (field, self.fields[field].kind.parts() - 1);
+        }
+    }
+
+    fn display(&self, disp: &mut D, state: &mut SharedState) -> Result<(), D::Error> {
+        let first = self.first_row();
+
+        for field in self.fields {
+            if field.row < first || field.row >= first + ROWS {
+                continue;
+            }
+            let row = field.row - first;
+
+            if !field.label.is_empty() {
+                disp.set_cursor_position(row, 0)?;
+                disp.write(field.label.as_bytes())?;
+            }
+
+            let mut text = TextBuf::new();
+            let _ = field.kind.write(state, &mut text);
+            let len = text.as_bytes().len().min(field.width as usize);
+            disp.set_cursor_position(row, field.col + field.width - len as u8)?;
+            disp.write(&text.as_bytes()[..len])?;
+        }
+
+        Ok(())
+    }
+
+    fn get_cursor_state(&self, _state: &SharedState) -> CursorState {
+        let field = self.field();
+        let row = field.row - self.first_row();
+        let col = field.col + field.width - 1 - field.kind.cursor_from_end(self.selected.1);
+
+        match self.in_edit {
+            true => CursorState::Blinking(row, col),
+            false => CursorState::Underline(row, col),
+        }
+    }
+
+    fn is_editing(&self) -> bool {
+        self.in_edit
+    }
+
+    fn at_first(&self) -> bool {
+        self.selected == (0, 0)
+    }
+
+    fn at_last(&self) -> bool {
+        let last = self.fields.len() - 1;
+        self.selected == (last, self.fields[last].kind.parts() - 1)
+    }
+
+    fn select_first(&mut self) {
+        self.selected = (0, 0);
+    }
+
+    fn select_last(&mut self) {
+        let last = self.fields.len() - 1;
+        self.selected = (last, self.fields[last].kind.parts() - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ClockState;
+    use crate::display::BufferedDisplay;
+    use crate::settings::Settings;
+    use crate::{Diagnostics, Stats};
+
+    type Disp = BufferedDisplay<4, 20>;
+
+    fn state() -> SharedState {
+        SharedState {
+            clock: ClockState::default(),
+            settings: Settings::default(),
+            measurement: None,
+            stats: Stats::default(),
+            diagnostics: Diagnostics::default(),
+        }
+    }
+
+    fn text(kind: &Kind, state: &SharedState) -> TextBuf {
+        let mut text = TextBuf::new();
+        kind.write(state, &mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn steps_stop_or_wrap_at_the_ends() {
+        assert_eq!(step(58u8, 0, 59, 1, true, true), 59);
+        assert_eq!(step(59u8, 0, 59, 1, true, true), 0);
+        assert_eq!(step(1u8, 1, 31, 1, true, false), 31);
+
+        assert_eq!(step(250u8, 0, 255, 8, false, true), 255);
+        assert_eq!(step(255u8, 0, 255, 8, false, true), 255);
+        assert_eq!(step(255u8, 0, 255, 8, false, false), 247);
+        assert_eq!(step(600u16, 500, 5000, 100, false, false), 500);
+        assert_eq!(step(500u16, 500, 5000, 100, false, false), 500);
+
+        // values outside of the range are brought back into it
+        assert_eq!(step(0u16, 1, 600, 1, false, true), 1);
+        assert_eq!(step(60u8, 0, 59, 1, true, false), 59);
+    }
+
+    #[test]
+    fn number_with_none_below_min() {
+        let kind = Kind::Number(Number {
+            get: |s| s.settings.contrast.map(u16::from),
+            set: |s, v| s.settings.contrast = v.map(|v| v as u8),
+            min: 0,
+            max: 255,
+            step: 8,
+            wrap: false,
+            none: Some("KEEP"),
+            format: Format::Digits(3),
+        });
+        let mut s = state();
+
+        assert_eq!(text(&kind, &s).as_bytes(), b"KEEP");
+        kind.step(&mut s, 0, false);
+        assert_eq!(s.settings.contrast, None);
+        kind.step(&mut s, 0, true);
+        kind.step(&mut s, 0, true);
+        assert_eq!(s.settings.contrast, Some(8));
+        assert_eq!(text(&kind, &s).as_bytes(), b"008");
+        kind.step(&mut s, 0, false);
+        kind.step(&mut s, 0, false);
+        assert_eq!(s.settings.contrast, None);
+    }
+
+    #[test]
+    fn formats_values() {
+        let mut s = state();
+        s.clock.month = 9;
+        s.clock.year = 21;
+        s.settings.long_press_duration = 2500;
+
+        let seconds = Kind::Number(Number {
+            get: |s| Some(s.settings.long_press_duration),
+            set: |_, _| (),
+            min: 0,
+            max: 5000,
+            step: 100,
+            wrap: false,
+            none: None,
+            format: Format::Seconds,
+        });
+        assert_eq!(text(&seconds, &s).as_bytes(), b"2.5s");
+
+        let date = Kind::Date(Date {
+            get: |s| [s.clock.day, s.clock.month, s.clock.year],
+            set: |_, _| (),
+        });
+        assert_eq!(text(&date, &s).as_bytes(), b"WED 01 SEP 2021");
+        s.clock.month = 13;
+        assert_eq!(text(&date, &s).as_bytes(), b"??? 01 ??? 2021");
+
+        let toggle = Kind::Toggle(Toggle {
+            get: |s| s.settings.alarms[0].enabled,
+            set: |s, on| s.settings.alarms[0].enabled = on,
+        });
+        assert_eq!(text(&toggle, &s).as_bytes(), b"OFF");
+        toggle.step(&mut s, 0, false);
+        assert_eq!(text(&toggle, &s).as_bytes(), b"ON");
+    }
+
+    #[test]
+    fn days_toggle_each_weekday() {
+        let kind = Kind::Days(Days {
+            get: |s| s.settings.alarms[0].days,
+            set: |s, days| s.settings.alarms[0].days = days,
+        });
+        let mut s = state();
+        s.settings.alarms[0].days = Weekdays::WORKDAYS;
+
+        assert_eq!(kind.parts(), 7);
+        assert_eq!(text(&kind, &s).as_bytes(), b"MTWTF--");
+        kind.step(&mut s, 6, true);
+        kind.step(&mut s, 0, false);
+        assert_eq!(text(&kind, &s).as_bytes(), b"-TWTF-S");
+        assert_eq!(kind.cursor_from_end(0), 6);
+        assert_eq!(kind.cursor_from_end(6), 0);
+    }
+
+    #[test]
+    fn list_wraps_and_shows_unknown_values() {
+        let kind = Kind::List(List {
+            get: |s| s.clock.weekday.wrapping_sub(1) as usize,
+            set: |s, i| s.clock.weekday = i as u8 + 1,
+            names: &["MON", "TUE", "WED", "THU", "FRI", "SAT", "SUN"],
+        });
+        let mut s = state();
+        s.clock.weekday = 1;
+
+        kind.step(&mut s, 0, false);
+        assert_eq!(s.clock.weekday, 7);
+        kind.step(&mut s, 0, true);
+        assert_eq!(s.clock.weekday, 1);
+
+        s.clock.weekday = 0;
+        assert_eq!(text(&kind, &s).as_bytes(), b"?");
+    }
+
+    #[test]
+    fn date_keeps_day_within_month() {
+        let kind = Kind::Date(Date {
+            get: |s| [s.clock.day, s.clock.month, s.clock.year],
+            set: |s, [day, month, year]| {
+                s.clock.day = day;
+                s.clock.month = month;
+                s.clock.year = year;
+            },
+        });
+        let mut s = state();
+        s.clock.day = 31;
+        s.clock.year = 24;
+
+        kind.step(&mut s, 1, true);
+        assert_eq!((s.clock.day, s.clock.month), (29, 2));
+        kind.step(&mut s, 2, true);
+        assert_eq!((s.clock.day, s.clock.year), (28, 25));
+
+        // the day wraps around at the end of the month
+        kind.step(&mut s, 0, true);
+        assert_eq!(s.clock.day, 1);
+        kind.step(&mut s, 0, false);
+        assert_eq!(s.clock.day, 28);
+
+        // years go beyond 2040 up to 2099
+        s.clock.year = 40;
+        kind.step(&mut s, 2, true);
+        assert_eq!(s.clock.year, 41);
+        s.clock.year = 99;
+        kind.step(&mut s, 2, true);
+        assert_eq!(s.clock.year, 0);
+    }
+
+    const FIELDS: &[Field] = &[
+        Field {
+            label: "At",
+            row: 0,
+            col: 3,
+            width: 5,
+            kind: Kind::Time(Time {
+                get: |s| [s.settings.alarms[3].hour, s.settings.alarms[3].minute, 0],
+                set: |s, [hour, minute, _]| {
+                    s.settings.alarms[3].hour = hour;
+                    s.settings.alarms[3].minute = minute;
+                },
+                seconds: false,
+            }),
+        },
+        Field {
+            label: "On",
+            row: 3,
+            col: 17,
+            width: 3,
+            kind: Kind::Toggle(Toggle {
+                get: |s| s.settings.alarms[3].enabled,
+                set: |s, on| s.settings.alarms[3].enabled = on,
+            }),
+        },
+    ];
+
+    #[test]
+    fn panel_selects_parts_and_scrolls() {
+        let mut panel = FieldPanel::new(FIELDS);
+        let mut s = state();
+        let mut disp = Disp::new();
+
+        assert!(Panel::<Disp>::at_first(&panel));
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(0, 4));
+
+        // the parts of the time are selected on their own
+        Panel::<Disp>::next(&mut panel, &mut s);
+        Panel::<Disp>::enter(&mut panel, &mut s);
+        Panel::<Disp>::previous(&mut panel, &mut s);
+        assert_eq!(s.settings.alarms[3].minute, 59);
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Blinking(0, 7));
+        Panel::<Disp>::leave(&mut panel, &mut s);
+
+        // the field on the fourth row scrolls the first one out of view
+        Panel::<Disp>::next(&mut panel, &mut s);
+        assert!(Panel::<Disp>::at_last(&panel));
+        assert!(Panel::<Disp>::get_cursor_state(&panel, &s) == CursorState::Underline(2, 19));
+        Panel::<Disp>::display(&panel, &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"                    ");
+        assert_eq!(&disp.rows()[2], b"On               OFF");
+
+        Panel::<Disp>::next(&mut panel, &mut s);
+        assert!(Panel::<Disp>::at_first(&panel));
+        disp.clear().unwrap();
+        Panel::<Disp>::display(&panel, &mut disp, &mut s).unwrap();
+        assert_eq!(&disp.rows()[0], b"At 07:59            ");
+    }
+}
@@ -0,0 +1,218 @@
+//! Parser of the RTTTL (Ring Tone Text Transfer Language) format of Nokia phones, which turns a
+//! ringtone like `"name:d=4,o=5,b=120:e,8f#,g.6,p"` into the notes of a `Sequencer`.
+//!
+//! The text has three sections separated by colons: a name, the default duration, octave and
+//! beats per minute, and the comma-separated notes. Each note is an optional duration, its letter
+//! or `p` for a rest, an optional `#`, an optional octave and an optional dot that makes it half
+//! as long again. The dot may also be written right after the letter.
+use heapless::Vec;
+
+use crate::tone::{Note, MAX_NOTES};
+
+/// What is wrong with a ringtone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The text does not have the three sections.
+    Format,
+    /// The defaults section has an unknown key or an invalid value.
+    Default,
+    /// The note with the index is invalid.
+    Note(usize),
+    /// There are more than `MAX_NOTES` notes.
+    TooLong,
+}
+
+/// Frequencies in Hz of the notes of the 8th octave, starting at C.
+const OCTAVE_8: [u16; 12] = [
+    4186, 4435, 4699, 4978, 5274, 5588, 5920, 6272, 6645, 7040, 7459, 7902,
+];
+
+/// The durations, octaves and tempos of the format.
+const DURATIONS: [u32; 6] = [1, 2, 4, 8, 16, 32];
+const OCTAVES: core::ops::RangeInclusive<u32> = 4..=7;
+const BPMS: core::ops::RangeInclusive<u32> = 25..=900;
+
+struct Defaults {
+    duration: u32,
+    octave: u32,
+    bpm: u32,
+}
+
+/// Parses a ringtone into its notes.
+pub fn parse(text: &str) -> Result<Vec<Note, MAX_NOTES>, Error> {
+    let mut sections = text.trim().splitn(3, ':');
+    let (_name, defaults, notes) = match (sections.next(), sections.next(), sections.next()) {
+        (Some(name), Some(defaults), Some(notes)) => (name, defaults, notes),
+        _ => return Err(Error::Format),
+    };
+    let defaults = parse_defaults(defaults)?;
+
+    let mut parsed = Vec::new();
+    for (i, note) in notes.split(',').enumerate() {
+        let note = parse_note(note.trim(), &defaults).ok_or(Error::Note(i))?;
+        parsed.push(note).map_err(|_| Error::TooLong)?;
+    }
+    Ok(parsed)
+}
+
+fn parse_defaults(text: &str) -> Result<Defaults, Error> {
+    let mut defaults = Defaults {
+        duration: 4,
+        octave: 6,
+        bpm: 63,
+    };
+    for entry in text.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (key, value) = entry.split_once('=').ok_or(Error::Default)?;
+        let value: u32 = value.trim().parse().map_err(|_| Error::Default)?;
+        match key.trim() {
+            "d" if DURATIONS.contains(&value) => defaults.duration = value,
+            "o" if OCTAVES.contains(&value) => defaults.octave = value,
+            "b" if BPMS.contains(&value) => defaults.bpm = value,
+            _ => return Err(Error::Default),
+        }
+    }
+    Ok(defaults)
+}
+
+fn parse_note(text: &str, defaults: &Defaults) -> Option<Note> {
+    let mut rest = text;
+
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let duration = match digits {
+        0 => defaults.duration,
+        _ => rest[..digits]
+            .parse()
+            .ok()
+            .filter(|d| DURATIONS.contains(d))?,
+    };
+    rest = &rest[digits..];
+
+    let mut chars = rest.chars();
+    let semitone = match chars.next()?.to_ascii_lowercase() {
+        'c' => Some(0),
+        'd' => Some(2),
+        'e' => Some(4),
+        'f' => Some(5),
+        'g' => Some(7),
+        'a' => Some(9),
+        'b' | 'h' => Some(11),
+        'p' => None,
+        _ => return None,
+    };
+    rest = chars.as_str();
+
+    let sharp = rest.starts_with('#');
+    if sharp {
+        rest = &rest[1..];
+    }
+    let mut dotted = rest.starts_with('.');
+    if dotted {
+        rest = &rest[1..];
+    }
+    let octave = match rest.chars().next() {
+        Some(c) if c.is_ascii_digit() => {
+            rest = &rest[1..];
+            Some(c as u32 - '0' as u32).filter(|o| OCTAVES.contains(o))?
+        }
+        _ => defaults.octave,
+    };
+    if rest == "." && !dotted {
+        dotted = true;
+        rest = "";
+    }
+    if !rest.is_empty() || (semitone.is_none() && sharp) {
+        return None;
+    }
+
+    // a whole note lasts four beats
+    let mut ms = 4 * 60_000 / (defaults.bpm * duration);
+    if dotted {
+        ms += ms / 2;
+    }
+    let freq = semitone.map(|s| {
+        // B# is the C of the next octave
+        let s = s + sharp as u32;
+        let shift = 8 - octave - s / 12;
+        (OCTAVE_8[s as usize % 12] + (1 << shift >> 1)) >> shift
+    });
+    Some(Note {
+        freq,
+        duration: ms as u16,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_follow_the_tempo() {
+        let notes = parse("test:d=4,o=5,b=120:c,8c,16c,1c,2p,32c").unwrap();
+        let durations: std::vec::Vec<u16> = notes.iter().map(|n| n.duration).collect();
+        assert_eq!(durations, [500, 250, 125, 2000, 1000, 62]);
+        assert_eq!(notes[4].freq, None);
+    }
+
+    #[test]
+    fn octaves_and_sharps() {
+        let notes = parse(":o=4:a,a5,a6,a7,c#,h,b#,g#7").unwrap();
+        let freqs: std::vec::Vec<Option<u16>> = notes.iter().map(|n| n.freq).collect();
+        assert_eq!(
+            freqs,
+            [
+                Some(440),
+                Some(880),
+                Some(1760),
+                Some(3520),
+                Some(277),
+                Some(494),
+                Some(523),
+                Some(3323)
+            ]
+        );
+    }
+
+    #[test]
+    fn dotted_notes() {
+        let notes = parse("x:d=8,b=100:e.,e6.,4p.,e.6").unwrap();
+        let durations: std::vec::Vec<u16> = notes.iter().map(|n| n.duration).collect();
+        assert_eq!(durations, [450, 450, 900, 450]);
+        assert_eq!(notes[1].freq, Some(1319));
+        assert_eq!(notes[3].freq, Some(1319));
+    }
+
+    #[test]
+    fn defaults_are_optional() {
+        let notes = parse("empty::a").unwrap();
+        assert_eq!(notes[0], Note::tone(1760, 952));
+        assert_eq!(parse(" spaces : d = 2 , b=240 : a , p ").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(parse("no sections"), Err(Error::Format));
+        assert_eq!(parse("x:d=4"), Err(Error::Format));
+        assert_eq!(parse("x:d=3:a"), Err(Error::Default));
+        assert_eq!(parse("x:o=9:a"), Err(Error::Default));
+        assert_eq!(parse("x:b=0:a"), Err(Error::Default));
+        assert_eq!(parse("x:q=4:a"), Err(Error::Default));
+        assert_eq!(parse("x:d4:a"), Err(Error::Default));
+        assert_eq!(parse("x::"), Err(Error::Note(0)));
+        assert_eq!(parse("x::a,x"), Err(Error::Note(1)));
+        assert_eq!(parse("x::a,,b"), Err(Error::Note(1)));
+        assert_eq!(parse("x::3a"), Err(Error::Note(0)));
+        assert_eq!(parse("x::a9"), Err(Error::Note(0)));
+        assert_eq!(parse("x::a..,b"), Err(Error::Note(0)));
+        assert_eq!(parse("x::p#"), Err(Error::Note(0)));
+        assert_eq!(parse("x::a5x"), Err(Error::Note(0)));
+
+        let mut long = std::string::String::from("x::a");
+        for _ in 0..MAX_NOTES {
+            long.push_str(",a");
+        }
+        assert_eq!(parse(&long), Err(Error::TooLong));
+    }
+}
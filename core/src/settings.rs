@@ -0,0 +1,973 @@
+//! The user settings stored on the SD-card, and the migrations of older versions of them.
+//!
+//! postcard does not store any field names, so the serialized `Settings` can only be read by the
+//! exact struct it was written from. Any change to `Settings` therefore needs a new
+//! `SETTINGS_VERSION`: move the current struct into a module named after its version, add a
+//! migration from it to the new `Settings` and a variant for it to `Versioned`, and add a fixture
+//! of it to the tests. Each version is only migrated to the one following it.
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alarm::{Alarm, Weekdays, ALARM_COUNT, MAX_VOLUME},
+    app::{LONG_PRESS_DURATION, POLL_FREQ},
+};
+
+/// Version of the serialized `Settings`.
+pub const SETTINGS_VERSION: u16 = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    /// Brightness of the display backlight during the day.
+    pub backlight_brightness: u8,
+    /// Contrast of the display, or `None` to keep the setting stored in the display module.
+    pub contrast: Option<u8>,
+    /// Type of the display module, 4 being the 4x20 characters display.
+    pub display_type: u8,
+    /// Number of MPU samples averaged into each logged `Measurement`.
+    pub mpu_samples: u16,
+    /// Milliseconds the button has to be held down to generate a long press.
+    pub long_press_duration: u16,
+    /// The alarms, of which the next due one is programmed into the RTC.
+    pub alarms: [Alarm; ALARM_COUNT],
+    /// Minutes a ringing alarm is snoozed for.
+    pub snooze_duration: u8,
+    /// Minutes over which the backlight brightens before an alarm, or 0 for no wake light.
+    pub sunrise_duration: u8,
+    /// Brightness of the display backlight during the night.
+    pub night_brightness: u8,
+    /// Hour the night starts at.
+    pub night_start: u8,
+    /// Hour the night ends at, which may be before `night_start` for a night spanning midnight.
+    pub night_end: u8,
+    /// Seconds without any input after which the backlight dims, or 0 to never dim it.
+    pub backlight_timeout: u16,
+    /// Brightness of the dimmed backlight, 0 turning it off.
+    pub idle_brightness: u8,
+}
+
+/// The tones the `Player` can play for the alarm, see `tone::melody`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AlarmTone {
+    #[default]
+    Beep,
+    Chirp,
+    Siren,
+    OdeToJoy,
+    FurElise,
+    MorningMood,
+}
+
+impl AlarmTone {
+    /// All tones, in the order of their discriminants.
+    pub const ALL: [AlarmTone; 6] = [
+        AlarmTone::Beep,
+        AlarmTone::Chirp,
+        AlarmTone::Siren,
+        AlarmTone::OdeToJoy,
+        AlarmTone::FurElise,
+        AlarmTone::MorningMood,
+    ];
+}
+
+impl Settings {
+    /// The long press duration in ticks of `POLL_FREQ`.
+    pub fn long_press_ticks(&self) -> u32 {
+        self.long_press_duration as u32 * POLL_FREQ / 1000
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            backlight_brightness: 64,
+            contrast: None,
+            display_type: 4,
+            mpu_samples: 10,
+            long_press_duration: (LONG_PRESS_DURATION * 1000) as u16,
+            alarms: [
+                Alarm {
+                    hour: 6,
+                    minute: 30,
+                    days: Weekdays::WORKDAYS,
+                    enabled: false,
+                    window: 0,
+                    tone: AlarmTone::Beep,
+                    volume: MAX_VOLUME,
+                },
+                Alarm {
+                    hour: 9,
+                    minute: 0,
+                    days: Weekdays::WEEKEND,
+                    enabled: false,
+                    window: 0,
+                    tone: AlarmTone::Beep,
+                    volume: MAX_VOLUME,
+                },
+                Alarm::default(),
+                Alarm::default(),
+            ],
+            snooze_duration: 9,
+            sunrise_duration: 0,
+            night_brightness: 16,
+            night_start: 22,
+            night_end: 7,
+            backlight_timeout: 60,
+            idle_brightness: 8,
+        }
+    }
+}
+
+/// The settings written before they were stored with a header, only holding the position of the
+/// log.
+pub mod v0 {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {
+        pub logger_block: u32,
+    }
+
+    impl From<Settings> for super::v1::Settings {
+        /// The position of the log is now found by the `Logger` itself.
+        fn from(_: Settings) -> Self {
+            Self {}
+        }
+    }
+}
+
+/// The settings without any fields, while the user preferences were still hard-coded.
+pub mod v1 {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {}
+
+    /// The user preferences that were hard-coded. The alarm was only programmed into the RTC.
+    pub const PREFERENCES: super::v2::Settings = super::v2::Settings {
+        backlight_brightness: 64,
+        contrast: None,
+        display_type: 4,
+        mpu_samples: 10,
+        long_press_duration: 2000,
+        alarm: super::v2::AlarmState {
+            hour: 6,
+            minute: 30,
+            enabled: false,
+        },
+    };
+
+    impl From<Settings> for super::v2::Settings {
+        fn from(_: Settings) -> Self {
+            PREFERENCES
+        }
+    }
+}
+
+/// The settings before the alarm tone could be selected.
+pub mod v2 {
+    use serde::{Deserialize, Serialize};
+
+    /// The single daily alarm, as it was programmed into the RTC.
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+    pub struct AlarmState {
+        pub hour: u8,
+        pub minute: u8,
+        pub enabled: bool,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {
+        pub backlight_brightness: u8,
+        pub contrast: Option<u8>,
+        pub display_type: u8,
+        pub mpu_samples: u16,
+        pub long_press_duration: u16,
+        pub alarm: AlarmState,
+    }
+
+    impl From<Settings> for super::v3::Settings {
+        /// The alarm played the beep, the only tone there was.
+        fn from(s: Settings) -> Self {
+            Self {
+                backlight_brightness: s.backlight_brightness,
+                contrast: s.contrast,
+                display_type: s.display_type,
+                mpu_samples: s.mpu_samples,
+                long_press_duration: s.long_press_duration,
+                alarm: s.alarm,
+                alarm_tone: super::AlarmTone::Beep,
+            }
+        }
+    }
+}
+
+/// The settings with a single daily alarm.
+pub mod v3 {
+    use serde::{Deserialize, Serialize};
+
+    use super::{v2::AlarmState, v4::Alarm, AlarmTone};
+    use crate::alarm::{Weekdays, ALARM_COUNT};
+
+    /// The alarms of the default v4 settings, the first of which is replaced by the daily alarm.
+    pub const DEFAULT_ALARMS: [Alarm; ALARM_COUNT] = [
+        Alarm {
+            hour: 6,
+            minute: 30,
+            days: Weekdays::WORKDAYS,
+            enabled: false,
+        },
+        Alarm {
+            hour: 9,
+            minute: 0,
+            days: Weekdays::WEEKEND,
+            enabled: false,
+        },
+        Alarm {
+            hour: 7,
+            minute: 0,
+            days: Weekdays::EVERY_DAY,
+            enabled: false,
+        },
+        Alarm {
+            hour: 7,
+            minute: 0,
+            days: Weekdays::EVERY_DAY,
+            enabled: false,
+        },
+    ];
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {
+        pub backlight_brightness: u8,
+        pub contrast: Option<u8>,
+        pub display_type: u8,
+        pub mpu_samples: u16,
+        pub long_press_duration: u16,
+        pub alarm: AlarmState,
+        pub alarm_tone: AlarmTone,
+    }
+
+    impl From<Settings> for super::v4::Settings {
+        /// The daily alarm becomes the first alarm, going off every day.
+        fn from(s: Settings) -> Self {
+            let mut alarms = DEFAULT_ALARMS;
+            alarms[0] = Alarm {
+                hour: s.alarm.hour,
+                minute: s.alarm.minute,
+                days: Weekdays::EVERY_DAY,
+                enabled: s.alarm.enabled,
+            };
+            Self {
+                backlight_brightness: s.backlight_brightness,
+                contrast: s.contrast,
+                display_type: s.display_type,
+                mpu_samples: s.mpu_samples,
+                long_press_duration: s.long_press_duration,
+                alarms,
+                alarm_tone: s.alarm_tone,
+            }
+        }
+    }
+}
+
+/// The settings before a ringing alarm could be snoozed.
+pub mod v4 {
+    use serde::{Deserialize, Serialize};
+
+    use super::AlarmTone;
+    use crate::alarm::{Weekdays, ALARM_COUNT};
+
+    /// An alarm before it had a wake window.
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Alarm {
+        pub hour: u8,
+        pub minute: u8,
+        pub days: Weekdays,
+        pub enabled: bool,
+    }
+
+    impl From<Alarm> for super::v8::Alarm {
+        /// The alarm only goes off at the set time.
+        fn from(a: Alarm) -> Self {
+            Self {
+                hour: a.hour,
+                minute: a.minute,
+                days: a.days,
+                enabled: a.enabled,
+                window: 0,
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {
+        pub backlight_brightness: u8,
+        pub contrast: Option<u8>,
+        pub display_type: u8,
+        pub mpu_samples: u16,
+        pub long_press_duration: u16,
+        pub alarms: [Alarm; ALARM_COUNT],
+        pub alarm_tone: AlarmTone,
+    }
+
+    /// Minutes of the default snooze of the v5 settings.
+    pub const SNOOZE_DURATION: u8 = 9;
+
+    impl From<Settings> for super::v5::Settings {
+        fn from(s: Settings) -> Self {
+            Self {
+                backlight_brightness: s.backlight_brightness,
+                contrast: s.contrast,
+                display_type: s.display_type,
+                mpu_samples: s.mpu_samples,
+                long_press_duration: s.long_press_duration,
+                alarms: s.alarms,
+                alarm_tone: s.alarm_tone,
+                snooze_duration: SNOOZE_DURATION,
+            }
+        }
+    }
+}
+
+/// The settings before the alarms had a wake window.
+pub mod v5 {
+    use serde::{Deserialize, Serialize};
+
+    use super::{v4::Alarm, AlarmTone};
+    use crate::alarm::ALARM_COUNT;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {
+        pub backlight_brightness: u8,
+        pub contrast: Option<u8>,
+        pub display_type: u8,
+        pub mpu_samples: u16,
+        pub long_press_duration: u16,
+        pub alarms: [Alarm; ALARM_COUNT],
+        pub alarm_tone: AlarmTone,
+        pub snooze_duration: u8,
+    }
+
+    impl From<Settings> for super::v6::Settings {
+        fn from(s: Settings) -> Self {
+            Self {
+                backlight_brightness: s.backlight_brightness,
+                contrast: s.contrast,
+                display_type: s.display_type,
+                mpu_samples: s.mpu_samples,
+                long_press_duration: s.long_press_duration,
+                alarms: s.alarms.map(From::from),
+                alarm_tone: s.alarm_tone,
+                snooze_duration: s.snooze_duration,
+            }
+        }
+    }
+}
+
+/// The settings before the wake light and the night brightness.
+pub mod v6 {
+    use serde::{Deserialize, Serialize};
+
+    use super::{v8::Alarm, AlarmTone};
+    use crate::alarm::ALARM_COUNT;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {
+        pub backlight_brightness: u8,
+        pub contrast: Option<u8>,
+        pub display_type: u8,
+        pub mpu_samples: u16,
+        pub long_press_duration: u16,
+        pub alarms: [Alarm; ALARM_COUNT],
+        pub alarm_tone: AlarmTone,
+        pub snooze_duration: u8,
+    }
+
+    /// Hour the night of the default v7 settings starts at.
+    pub const NIGHT_START: u8 = 22;
+    /// Hour the night of the default v7 settings ends at.
+    pub const NIGHT_END: u8 = 7;
+
+    impl From<Settings> for super::v7::Settings {
+        /// The backlight had the same brightness day and night, and there was no wake light.
+        fn from(s: Settings) -> Self {
+            Self {
+                backlight_brightness: s.backlight_brightness,
+                contrast: s.contrast,
+                display_type: s.display_type,
+                mpu_samples: s.mpu_samples,
+                long_press_duration: s.long_press_duration,
+                alarms: s.alarms,
+                alarm_tone: s.alarm_tone,
+                snooze_duration: s.snooze_duration,
+                sunrise_duration: 0,
+                night_brightness: s.backlight_brightness,
+                night_start: NIGHT_START,
+                night_end: NIGHT_END,
+            }
+        }
+    }
+}
+
+/// The settings before the backlight dimmed without any input.
+pub mod v7 {
+    use serde::{Deserialize, Serialize};
+
+    use super::{v8::Alarm, AlarmTone};
+    use crate::alarm::ALARM_COUNT;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {
+        pub backlight_brightness: u8,
+        pub contrast: Option<u8>,
+        pub display_type: u8,
+        pub mpu_samples: u16,
+        pub long_press_duration: u16,
+        pub alarms: [Alarm; ALARM_COUNT],
+        pub alarm_tone: AlarmTone,
+        pub snooze_duration: u8,
+        pub sunrise_duration: u8,
+        pub night_brightness: u8,
+        pub night_start: u8,
+        pub night_end: u8,
+    }
+
+    /// Brightness of the dimmed backlight of the default v8 settings.
+    pub const IDLE_BRIGHTNESS: u8 = 8;
+
+    impl From<Settings> for super::v8::Settings {
+        /// The backlight stayed on.
+        fn from(s: Settings) -> Self {
+            Self {
+                backlight_brightness: s.backlight_brightness,
+                contrast: s.contrast,
+                display_type: s.display_type,
+                mpu_samples: s.mpu_samples,
+                long_press_duration: s.long_press_duration,
+                alarms: s.alarms,
+                alarm_tone: s.alarm_tone,
+                snooze_duration: s.snooze_duration,
+                sunrise_duration: s.sunrise_duration,
+                night_brightness: s.night_brightness,
+                night_start: s.night_start,
+                night_end: s.night_end,
+                backlight_timeout: 0,
+                idle_brightness: IDLE_BRIGHTNESS,
+            }
+        }
+    }
+}
+
+/// The settings before each alarm had its own tone.
+pub mod v8 {
+    use serde::{Deserialize, Serialize};
+
+    use super::AlarmTone;
+    use crate::alarm::{Weekdays, ALARM_COUNT};
+
+    /// An alarm before it had its own tone.
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Alarm {
+        pub hour: u8,
+        pub minute: u8,
+        pub days: Weekdays,
+        pub enabled: bool,
+        pub window: u8,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {
+        pub backlight_brightness: u8,
+        pub contrast: Option<u8>,
+        pub display_type: u8,
+        pub mpu_samples: u16,
+        pub long_press_duration: u16,
+        pub alarms: [Alarm; ALARM_COUNT],
+        pub alarm_tone: AlarmTone,
+        pub snooze_duration: u8,
+        pub sunrise_duration: u8,
+        pub night_brightness: u8,
+        pub night_start: u8,
+        pub night_end: u8,
+        pub backlight_timeout: u16,
+        pub idle_brightness: u8,
+    }
+
+    impl From<Settings> for super::v9::Settings {
+        /// All alarms play the tone that was shared by them.
+        fn from(s: Settings) -> Self {
+            Self {
+                backlight_brightness: s.backlight_brightness,
+                contrast: s.contrast,
+                display_type: s.display_type,
+                mpu_samples: s.mpu_samples,
+                long_press_duration: s.long_press_duration,
+                alarms: s.alarms.map(|a| super::v9::Alarm {
+                    hour: a.hour,
+                    minute: a.minute,
+                    days: a.days,
+                    enabled: a.enabled,
+                    window: a.window,
+                    tone: s.alarm_tone,
+                }),
+                snooze_duration: s.snooze_duration,
+                sunrise_duration: s.sunrise_duration,
+                night_brightness: s.night_brightness,
+                night_start: s.night_start,
+                night_end: s.night_end,
+                backlight_timeout: s.backlight_timeout,
+                idle_brightness: s.idle_brightness,
+            }
+        }
+    }
+}
+
+/// The settings before each alarm had its own volume.
+pub mod v9 {
+    use serde::{Deserialize, Serialize};
+
+    use super::AlarmTone;
+    use crate::alarm::{Weekdays, ALARM_COUNT, MAX_VOLUME};
+
+    /// An alarm before it had its own volume.
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Alarm {
+        pub hour: u8,
+        pub minute: u8,
+        pub days: Weekdays,
+        pub enabled: bool,
+        pub window: u8,
+        pub tone: AlarmTone,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Settings {
+        pub backlight_brightness: u8,
+        pub contrast: Option<u8>,
+        pub display_type: u8,
+        pub mpu_samples: u16,
+        pub long_press_duration: u16,
+        pub alarms: [Alarm; ALARM_COUNT],
+        pub snooze_duration: u8,
+        pub sunrise_duration: u8,
+        pub night_brightness: u8,
+        pub night_start: u8,
+        pub night_end: u8,
+        pub backlight_timeout: u16,
+        pub idle_brightness: u8,
+    }
+
+    impl From<Settings> for super::Settings {
+        /// The alarms ring at full volume, as the buzzer always did.
+        fn from(s: Settings) -> Self {
+            Self {
+                backlight_brightness: s.backlight_brightness,
+                contrast: s.contrast,
+                display_type: s.display_type,
+                mpu_samples: s.mpu_samples,
+                long_press_duration: s.long_press_duration,
+                alarms: s.alarms.map(|a| crate::alarm::Alarm {
+                    hour: a.hour,
+                    minute: a.minute,
+                    days: a.days,
+                    enabled: a.enabled,
+                    window: a.window,
+                    tone: a.tone,
+                    volume: MAX_VOLUME,
+                }),
+                snooze_duration: s.snooze_duration,
+                sunrise_duration: s.sunrise_duration,
+                night_brightness: s.night_brightness,
+                night_start: s.night_start,
+                night_end: s.night_end,
+                backlight_timeout: s.backlight_timeout,
+                idle_brightness: s.idle_brightness,
+            }
+        }
+    }
+}
+
+/// `Settings` of any known version.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Versioned {
+    V0(v0::Settings),
+    V1(v1::Settings),
+    V2(v2::Settings),
+    V3(v3::Settings),
+    V4(v4::Settings),
+    V5(v5::Settings),
+    V6(v6::Settings),
+    V7(v7::Settings),
+    V8(v8::Settings),
+    V9(v9::Settings),
+    Current(Settings),
+}
+
+impl Versioned {
+    /// Deserializes the settings of the given version. Returns `None` for unknown versions or
+    /// data that cannot be decoded.
+    pub fn parse(version: u16, data: &[u8]) -> Option<Self> {
+        use postcard::from_bytes;
+        match version {
+            0 => from_bytes(data).ok().map(Self::V0),
+            1 => from_bytes(data).ok().map(Self::V1),
+            2 => from_bytes(data).ok().map(Self::V2),
+            3 => from_bytes(data).ok().map(Self::V3),
+            4 => from_bytes(data).ok().map(Self::V4),
+            5 => from_bytes(data).ok().map(Self::V5),
+            6 => from_bytes(data).ok().map(Self::V6),
+            7 => from_bytes(data).ok().map(Self::V7),
+            8 => from_bytes(data).ok().map(Self::V8),
+            9 => from_bytes(data).ok().map(Self::V9),
+            SETTINGS_VERSION => from_bytes(data).ok().map(Self::Current),
+            _ => None,
+        }
+    }
+
+    /// Migrates the settings to the version following theirs.
+    fn migrate(self) -> Self {
+        match self {
+            Self::V0(s) => Self::V1(s.into()),
+            Self::V1(s) => Self::V2(s.into()),
+            Self::V2(s) => Self::V3(s.into()),
+            Self::V3(s) => Self::V4(s.into()),
+            Self::V4(s) => Self::V5(s.into()),
+            Self::V5(s) => Self::V6(s.into()),
+            Self::V6(s) => Self::V7(s.into()),
+            Self::V7(s) => Self::V8(s.into()),
+            Self::V8(s) => Self::V9(s.into()),
+            Self::V9(s) => Self::Current(s.into()),
+            Self::Current(s) => Self::Current(s),
+        }
+    }
+
+    /// Migrates the settings one version at a time until they reach the current one.
+    pub fn into_current(self) -> Settings {
+        let mut settings = self;
+        loop {
+            match settings {
+                Self::Current(s) => return s,
+                older => settings = older.migrate(),
+            }
+        }
+    }
+}
+
+/// Decodes `Settings` serialized as the given version, migrating older versions to the current
+/// one. Returns `None` for unknown versions or data that cannot be decoded.
+pub fn decode(version: u16, data: &[u8]) -> Option<Settings> {
+    Versioned::parse(version, data).map(Versioned::into_current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_version_roundtrip() {
+        let mut settings = Settings {
+            contrast: Some(100),
+            ..Settings::default()
+        };
+        settings.alarms[2] = Alarm {
+            hour: 6,
+            minute: 45,
+            days: Weekdays::from_bits(0b101_0101),
+            enabled: true,
+            window: 30,
+            tone: AlarmTone::FurElise,
+            volume: 40,
+        };
+        let data = postcard::to_stdvec(&settings).unwrap();
+        assert_eq!(decode(SETTINGS_VERSION, &data), Some(settings));
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let data = postcard::to_stdvec(&Settings::default()).unwrap();
+        assert_eq!(decode(SETTINGS_VERSION + 1, &data), None);
+    }
+
+    #[test]
+    fn migrates_v0() {
+        assert_eq!(decode(0, &[0x2a, 0, 0, 0]), decode(1, &[]));
+        assert_eq!(decode(0, &[0x2a]), None);
+    }
+
+    #[test]
+    fn migrates_v1() {
+        // the hard-coded preferences, with the backlight as it was before it dimmed at night or
+        // when idle
+        let settings = decode(1, &[]).unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                backlight_brightness: 64,
+                contrast: None,
+                display_type: 4,
+                mpu_samples: 10,
+                long_press_duration: 2000,
+                alarms: settings.alarms,
+                snooze_duration: 9,
+                sunrise_duration: 0,
+                night_brightness: 64,
+                night_start: 22,
+                night_end: 7,
+                backlight_timeout: 0,
+                idle_brightness: 8,
+            }
+        );
+        assert_eq!(
+            settings.alarms[0],
+            Alarm {
+                hour: 6,
+                minute: 30,
+                days: Weekdays::EVERY_DAY,
+                enabled: false,
+                window: 0,
+                tone: AlarmTone::Beep,
+                volume: MAX_VOLUME,
+            }
+        );
+    }
+
+    #[test]
+    fn migrates_v2() {
+        let data = [
+            0x80, 0x01, 0x20, 0x04, 0x14, 0x00, 0xdc, 0x05, 0x06, 0x2d, 0x01,
+        ];
+        let settings = decode(2, &data).unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                backlight_brightness: 128,
+                contrast: Some(32),
+                display_type: 4,
+                mpu_samples: 20,
+                long_press_duration: 1500,
+                alarms: settings.alarms,
+                snooze_duration: 9,
+                sunrise_duration: 0,
+                night_brightness: 128,
+                night_start: 22,
+                night_end: 7,
+                backlight_timeout: 0,
+                idle_brightness: 8,
+            }
+        );
+        assert_eq!(
+            settings.alarms[0],
+            Alarm {
+                hour: 6,
+                minute: 45,
+                days: Weekdays::EVERY_DAY,
+                enabled: true,
+                window: 0,
+                tone: AlarmTone::Beep,
+                volume: MAX_VOLUME,
+            }
+        );
+        assert_eq!(
+            settings.alarms[1],
+            Alarm {
+                hour: 9,
+                minute: 0,
+                days: Weekdays::WEEKEND,
+                enabled: false,
+                window: 0,
+                tone: AlarmTone::Beep,
+                volume: MAX_VOLUME,
+            }
+        );
+        assert_eq!(
+            settings.alarms[3],
+            Alarm {
+                hour: 7,
+                minute: 0,
+                days: Weekdays::EVERY_DAY,
+                enabled: false,
+                window: 0,
+                tone: AlarmTone::Beep,
+                volume: MAX_VOLUME,
+            }
+        );
+    }
+
+    #[test]
+    fn migrates_v3() {
+        let data = [
+            0x80, 0x01, 0x20, 0x04, 0x14, 0x00, 0xdc, 0x05, 0x06, 0x2d, 0x00, 0x02,
+        ];
+        let settings = decode(3, &data).unwrap();
+        assert_eq!(
+            settings.alarms[0],
+            Alarm {
+                hour: 6,
+                minute: 45,
+                days: Weekdays::EVERY_DAY,
+                enabled: false,
+                window: 0,
+                tone: AlarmTone::Siren,
+                volume: MAX_VOLUME,
+            }
+        );
+        assert_eq!(settings.alarms[3].tone, AlarmTone::Siren);
+        assert_eq!(settings.long_press_duration, 1500);
+    }
+
+    #[test]
+    fn migrates_v4() {
+        // the v3 fixture with the four alarms, the first one at 06:45 on workdays
+        let mut data = vec![0x80, 0x01, 0x20, 0x04, 0x14, 0x00, 0xdc, 0x05];
+        data.extend_from_slice(&[0x06, 0x2d, 0x1f, 0x01]);
+        for _ in 1..ALARM_COUNT {
+            data.extend_from_slice(&[0x07, 0x00, 0x7f, 0x00]);
+        }
+        data.push(0x01);
+
+        let settings = decode(4, &data).unwrap();
+        assert_eq!(
+            settings.alarms[0],
+            Alarm {
+                hour: 6,
+                minute: 45,
+                days: Weekdays::WORKDAYS,
+                enabled: true,
+                window: 0,
+                tone: AlarmTone::Chirp,
+                volume: MAX_VOLUME,
+            }
+        );
+        assert_eq!(
+            settings.alarms[3],
+            Alarm {
+                hour: 7,
+                minute: 0,
+                days: Weekdays::EVERY_DAY,
+                enabled: false,
+                window: 0,
+                tone: AlarmTone::Chirp,
+                volume: MAX_VOLUME,
+            }
+        );
+        assert_eq!(settings.snooze_duration, 9);
+    }
+
+    #[test]
+    fn migrates_v5() {
+        // the v4 fixture with a snooze duration of 5 minutes
+        let mut data = vec![0x80, 0x01, 0x20, 0x04, 0x14, 0x00, 0xdc, 0x05];
+        data.extend_from_slice(&[0x06, 0x2d, 0x1f, 0x01]);
+        for _ in 1..ALARM_COUNT {
+            data.extend_from_slice(&[0x07, 0x00, 0x7f, 0x00]);
+        }
+        data.extend_from_slice(&[0x01, 0x05]);
+
+        let settings = decode(5, &data).unwrap();
+        assert_eq!(
+            settings.alarms[0],
+            Alarm {
+                hour: 6,
+                minute: 45,
+                days: Weekdays::WORKDAYS,
+                enabled: true,
+                window: 0,
+                tone: AlarmTone::Chirp,
+                volume: MAX_VOLUME,
+            }
+        );
+        assert_eq!(
+            settings.alarms[3],
+            Alarm {
+                hour: 7,
+                minute: 0,
+                days: Weekdays::EVERY_DAY,
+                enabled: false,
+                window: 0,
+                tone: AlarmTone::Chirp,
+                volume: MAX_VOLUME,
+            }
+        );
+        assert_eq!(settings.snooze_duration, 5);
+    }
+
+    #[test]
+    fn migrates_v6() {
+        // the v5 fixture with a wake window of 30 minutes for the first alarm
+        let mut data = vec![0x80, 0x01, 0x20, 0x04, 0x14, 0x00, 0xdc, 0x05];
+        data.extend_from_slice(&[0x06, 0x2d, 0x1f, 0x01, 0x1e]);
+        for _ in 1..ALARM_COUNT {
+            data.extend_from_slice(&[0x07, 0x00, 0x7f, 0x00, 0x00]);
+        }
+        data.extend_from_slice(&[0x01, 0x05]);
+
+        let settings = decode(6, &data).unwrap();
+        assert_eq!(settings.alarms[0].window, 30);
+        assert_eq!(settings.alarms[3].tone, AlarmTone::Chirp);
+        assert_eq!(settings.snooze_duration, 5);
+        assert_eq!(settings.sunrise_duration, 0);
+        assert_eq!(settings.night_brightness, 128);
+    }
+
+    #[test]
+    fn migrates_v7() {
+        let mut data = vec![0x80, 0x01, 0x20, 0x04, 0x14, 0x00, 0xdc, 0x05];
+        for _ in 0..ALARM_COUNT {
+            data.extend_from_slice(&[0x07, 0x00, 0x7f, 0x00, 0x00]);
+        }
+        // a sunrise of 15 minutes and a night from 23 to 6 at brightness 4
+        data.extend_from_slice(&[0x01, 0x05, 0x0f, 0x04, 0x17, 0x06]);
+
+        let settings = decode(7, &data).unwrap();
+        assert_eq!(settings.sunrise_duration, 15);
+        assert_eq!(settings.night_brightness, 4);
+        assert_eq!((settings.night_start, settings.night_end), (23, 6));
+        assert_eq!(settings.backlight_timeout, 0);
+    }
+
+    #[test]
+    fn migrates_v8() {
+        let mut data = vec![0x80, 0x01, 0x20, 0x04, 0x14, 0x00, 0xdc, 0x05];
+        for _ in 0..ALARM_COUNT {
+            data.extend_from_slice(&[0x07, 0x00, 0x7f, 0x00, 0x00]);
+        }
+        // the siren for all alarms and the backlight dimmed to 4 after 30 seconds
+        data.extend_from_slice(&[0x02, 0x05, 0x0f, 0x04, 0x17, 0x06, 0x1e, 0x00, 0x04]);
+
+        let settings = decode(8, &data).unwrap();
+        assert!(settings.alarms.iter().all(|a| a.tone == AlarmTone::Siren));
+        assert_eq!(settings.backlight_timeout, 30);
+        assert_eq!(settings.idle_brightness, 4);
+    }
+
+    #[test]
+    fn migrates_v9() {
+        let mut data = vec![0x80, 0x01, 0x20, 0x04, 0x14, 0x00, 0xdc, 0x05];
+        // Fur Elise for the first alarm
+        data.extend_from_slice(&[0x06, 0x2d, 0x1f, 0x01, 0x1e, 0x04]);
+        for _ in 1..ALARM_COUNT {
+            data.extend_from_slice(&[0x07, 0x00, 0x7f, 0x00, 0x00, 0x00]);
+        }
+        data.extend_from_slice(&[0x05, 0x0f, 0x04, 0x17, 0x06, 0x1e, 0x00, 0x04]);
+
+        let settings = decode(9, &data).unwrap();
+        assert_eq!(settings.alarms[0].tone, AlarmTone::FurElise);
+        assert_eq!(settings.alarms[0].window, 30);
+        assert!(settings.alarms.iter().all(|a| a.volume == MAX_VOLUME));
+        assert_eq!(settings.idle_brightness, 4);
+    }
+
+    #[test]
+    fn long_press_ticks() {
+        let settings = Settings {
+            long_press_duration: 1500,
+            ..Settings::default()
+        };
+        assert_eq!(settings.long_press_ticks(), 15);
+        assert_eq!(
+            Settings::default().long_press_ticks(),
+            LONG_PRESS_DURATION * POLL_FREQ
+        );
+    }
+}
@@ -0,0 +1,651 @@
+//! Layout of the data stored on the SD-card and the block devices it can be stored on.
+use crc::{Crc, CRC_32_ISCSI};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::{self, Settings, SETTINGS_VERSION};
+
+/// Size in bytes of one SD-card block.
+pub const SD_BLOCK_SIZE: usize = 512;
+
+/// First of the two blocks holding the `Settings`.
+pub const SETTINGS_BLOCK_IDX: u32 = 1;
+
+/// The blocks the `Settings` are alternately written to, so that one valid copy always remains
+/// if a write is interrupted.
+pub const SETTINGS_BLOCKS: [u32; 2] = [SETTINGS_BLOCK_IDX, SETTINGS_BLOCK_IDX + 1];
+
+/// Block holding the `LogHeader` of the log written by the `Logger`.
+pub const LOGGER_BLOCK_START_IDX: u32 = 10;
+
+/// First block of log entries, following the `LogHeader`.
+pub const LOGGER_DATA_START_IDX: u32 = LOGGER_BLOCK_START_IDX + 1;
+
+/// Block holding the `logger::LegacyLog` on a card that still holds the log written before the
+/// `LogHeader` was introduced.
+pub const LEGACY_LOG_BLOCK_IDX: u32 = LOGGER_BLOCK_START_IDX - 1;
+
+/// A storage device that is read and written in blocks of `SD_BLOCK_SIZE` bytes.
+pub trait BlockDevice {
+    type Error;
+
+    fn read_block(&mut self, addr: u32, block: &mut [u8; SD_BLOCK_SIZE])
+        -> Result<(), Self::Error>;
+    fn write_block(&mut self, addr: u32, block: &[u8; SD_BLOCK_SIZE]) -> Result<(), Self::Error>;
+
+    /// The total number of blocks available on the device.
+    fn block_count(&self) -> u32;
+}
+
+#[derive(Debug)]
+pub enum Error<E> {
+    DeviceError(E),
+    PostcardError(postcard::Error),
+    /// There are no more blocks left on the device to write to.
+    DeviceFull,
+    /// The device holds a log in a format this version cannot append to. Logs written before the
+    /// `LogHeader` was introduced are reported as version 0 if where they end is no longer known.
+    UnsupportedLogVersion {
+        version: u16,
+        schema: u16,
+    },
+}
+
+impl<E> Error<E> {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::DeviceError(_) => ErrorKind::Device,
+            Error::PostcardError(_) => ErrorKind::Encoding,
+            Error::DeviceFull => ErrorKind::DeviceFull,
+            Error::UnsupportedLogVersion { version, .. } => {
+                ErrorKind::UnsupportedLogVersion { version: *version }
+            }
+        }
+    }
+}
+
+/// What kind of `Error` occurred, without the error of the device, to be kept for the
+/// diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorKind {
+    Device,
+    Encoding,
+    DeviceFull,
+    UnsupportedLogVersion { version: u16 },
+}
+
+/// Identifies a block holding a copy of the `Settings`.
+const SETTINGS_MAGIC: [u8; 4] = *b"DSAS";
+
+/// Size of the serialized `SettingsHeader`.
+const SETTINGS_HEADER_SIZE: usize = 12;
+
+/// Size of the CRC following the serialized `Settings`.
+const SETTINGS_CRC_SIZE: usize = 4;
+
+/// Checksum over the header and the serialized `Settings` of each copy.
+const SETTINGS_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+/// Stored at the start of each copy of the `Settings`, followed by the serialized `Settings` and
+/// the CRC.
+#[derive(Serialize, Deserialize, Debug)]
+struct SettingsHeader {
+    magic: [u8; 4],
+    /// The `SETTINGS_VERSION` the `Settings` were serialized with.
+    version: u16,
+    /// Incremented for every write, to tell which of the copies is the newest.
+    seq: u32,
+    /// Length of the serialized `Settings`.
+    len: u16,
+}
+
+/// Where `load_settings` found the `Settings`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SettingsSource {
+    /// The newest valid copy, in the given block.
+    Stored { block: u32, seq: u32 },
+    /// The settings written before they were stored with a header, in `SETTINGS_BLOCK_IDX`.
+    Legacy,
+    /// None of the blocks held a valid copy, so the defaults are used.
+    Default,
+}
+
+/// Reads the copy of the `Settings` in the block, returning `None` if it is missing or damaged.
+fn read_settings_copy<B: BlockDevice>(
+    dev: &mut B,
+    addr: u32,
+) -> Result<Option<(u32, Settings)>, Error<B::Error>> {
+    let mut block = [0u8; SD_BLOCK_SIZE];
+    dev.read_block(addr, &mut block)
+        .map_err(Error::DeviceError)?;
+
+    let header = match postcard::from_bytes::<SettingsHeader>(&block[..SETTINGS_HEADER_SIZE]) {
+        Ok(header) if header.magic == SETTINGS_MAGIC => header,
+        _ => return Ok(None),
+    };
+
+    let end = SETTINGS_HEADER_SIZE + header.len as usize;
+    if end + SETTINGS_CRC_SIZE > SD_BLOCK_SIZE {
+        return Ok(None);
+    }
+
+    let mut crc = [0u8; SETTINGS_CRC_SIZE];
+    crc.copy_from_slice(&block[end..end + SETTINGS_CRC_SIZE]);
+    if SETTINGS_CRC.checksum(&block[..end]) != u32::from_le_bytes(crc) {
+        return Ok(None);
+    }
+
+    Ok(
+        settings::decode(header.version, &block[SETTINGS_HEADER_SIZE..end])
+            .map(|settings| (header.seq, settings)),
+    )
+}
+
+/// Reads the settings written before they were stored with a header, ie a bare `v0::Settings`.
+/// These always pointed into the log and were followed by zeroes only, which tells them apart
+/// from a blank block.
+fn read_v0_settings<B: BlockDevice>(
+    dev: &mut B,
+) -> Result<Option<settings::v0::Settings>, Error<B::Error>> {
+    let mut block = [0u8; SD_BLOCK_SIZE];
+    dev.read_block(SETTINGS_BLOCK_IDX, &mut block)
+        .map_err(Error::DeviceError)?;
+
+    Ok(
+        match postcard::take_from_bytes::<settings::v0::Settings>(&block) {
+            Ok((legacy, rest))
+                if legacy.logger_block >= LOGGER_BLOCK_START_IDX
+                    && rest.iter().all(|&b| b == 0) =>
+            {
+                Some(legacy)
+            }
+            _ => None,
+        },
+    )
+}
+
+fn read_legacy_settings<B: BlockDevice>(dev: &mut B) -> Result<Option<Settings>, Error<B::Error>> {
+    Ok(read_v0_settings(dev)?
+        .map(settings::Versioned::V0)
+        .map(settings::Versioned::into_current))
+}
+
+/// The block following the log written before the `LogHeader` was introduced, as stored in the
+/// settings of the time. `None` once they have been replaced by settings with a header.
+pub fn legacy_log_end<B: BlockDevice>(dev: &mut B) -> Result<Option<u32>, Error<B::Error>> {
+    Ok(read_v0_settings(dev)?.map(|s| s.logger_block))
+}
+
+/// Loads the newest valid copy of the `Settings`, migrated to the current version, or the
+/// defaults if there is none.
+pub fn load_settings<B: BlockDevice>(
+    dev: &mut B,
+) -> Result<(Settings, SettingsSource), Error<B::Error>> {
+    let mut newest: Option<(u32, u32, Settings)> = None;
+
+    for &block in SETTINGS_BLOCKS.iter() {
+        if let Some((seq, settings)) = read_settings_copy(dev, block)? {
+            match newest {
+                Some((newest_seq, _, _)) if newest_seq >= seq => {}
+                _ => newest = Some((seq, block, settings)),
+            }
+        }
+    }
+
+    if let Some((seq, block, settings)) = newest {
+        return Ok((settings, SettingsSource::Stored { block, seq }));
+    }
+
+    Ok(match read_legacy_settings(dev)? {
+        Some(settings) => (settings, SettingsSource::Legacy),
+        None => (Settings::default(), SettingsSource::Default),
+    })
+}
+
+/// Stores the `Settings` in the block not holding the copy at `last`, so that it stays valid if
+/// the write is interrupted. Returns where the new copy was stored.
+pub fn store_settings<B: BlockDevice>(
+    dev: &mut B,
+    settings: &Settings,
+    last: SettingsSource,
+) -> Result<SettingsSource, Error<B::Error>> {
+    let (block, seq) = match last {
+        SettingsSource::Stored { block, seq } if block == SETTINGS_BLOCKS[0] => {
+            (SETTINGS_BLOCKS[1], seq.wrapping_add(1))
+        }
+        SettingsSource::Stored { seq, .. } => (SETTINGS_BLOCKS[0], seq.wrapping_add(1)),
+        SettingsSource::Legacy | SettingsSource::Default => (SETTINGS_BLOCKS[0], 0),
+    };
+
+    let mut data = [0u8; SD_BLOCK_SIZE];
+    let len = postcard::to_slice(
+        settings,
+        &mut data[SETTINGS_HEADER_SIZE..SD_BLOCK_SIZE - SETTINGS_CRC_SIZE],
+    )
+    .map_err(Error::PostcardError)?
+    .len();
+
+    let header = SettingsHeader {
+        magic: SETTINGS_MAGIC,
+        version: SETTINGS_VERSION,
+        seq,
+        len: len as u16,
+    };
+    postcard::to_slice(&header, &mut data[..SETTINGS_HEADER_SIZE]).map_err(Error::PostcardError)?;
+
+    let end = SETTINGS_HEADER_SIZE + len;
+    let crc = SETTINGS_CRC.checksum(&data[..end]);
+    data[end..end + SETTINGS_CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+
+    dev.write_block(block, &data).map_err(Error::DeviceError)?;
+
+    Ok(SettingsSource::Stored { block, seq })
+}
+
+/// Holds the current `Settings` and writes them back to the device once they have not changed for
+/// a while, so that stepping through a value in the user interface results in a single write.
+pub struct SettingsWriter {
+    settings: Settings,
+    stored: Settings,
+    source: SettingsSource,
+    /// Ticks the settings have to stay unchanged before they are written.
+    delay: u32,
+    unchanged: u32,
+}
+
+impl SettingsWriter {
+    /// Starts from the settings returned by `load_settings`.
+    pub fn new(settings: Settings, source: SettingsSource, delay: u32) -> Self {
+        Self {
+            settings,
+            stored: settings,
+            source,
+            delay,
+            unchanged: 0,
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Where the settings were last loaded from or written to.
+    pub fn source(&self) -> SettingsSource {
+        self.source
+    }
+
+    /// Returns `true` if the settings have changed since they were last written.
+    pub fn is_pending(&self) -> bool {
+        self.settings != self.stored
+    }
+
+    /// Replaces the settings, restarting the delay if they changed.
+    pub fn update(&mut self, settings: Settings) {
+        if settings != self.settings {
+            self.settings = settings;
+            self.unchanged = 0;
+        }
+    }
+
+    /// Should be called on every tick. Writes the settings once they have been unchanged for the
+    /// delay and returns `true` if they were written. A failed write is retried after another
+    /// delay.
+    pub fn tick<B: BlockDevice>(&mut self, dev: &mut B) -> Result<bool, Error<B::Error>> {
+        if !self.is_pending() {
+            return Ok(false);
+        }
+
+        self.unchanged += 1;
+        if self.unchanged < self.delay {
+            return Ok(false);
+        }
+
+        self.unchanged = 0;
+        self.source = store_settings(dev, &self.settings, self.source)?;
+        self.stored = self.settings;
+        Ok(true)
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+pub use self::std_devices::*;
+
+#[cfg(any(test, feature = "std"))]
+mod std_devices {
+    use std::{
+        fs::File,
+        io::{self, Read, Seek, SeekFrom, Write},
+        vec::Vec,
+    };
+
+    use super::{BlockDevice, SD_BLOCK_SIZE};
+
+    /// Returned when accessing a block outside of a `MemBlockDevice`.
+    #[derive(Debug)]
+    pub struct OutOfBounds(pub u32);
+
+    /// A block device kept in RAM, initially filled with zeroes.
+    pub struct MemBlockDevice {
+        blocks: Vec<[u8; SD_BLOCK_SIZE]>,
+    }
+
+    impl MemBlockDevice {
+        pub fn new(block_count: u32) -> Self {
+            MemBlockDevice {
+                blocks: vec![[0u8; SD_BLOCK_SIZE]; block_count as usize],
+            }
+        }
+
+        /// Direct access to the stored blocks, e.g. for corrupting them on purpose.
+        pub fn blocks_mut(&mut self) -> &mut [[u8; SD_BLOCK_SIZE]] {
+            &mut self.blocks
+        }
+    }
+
+    impl BlockDevice for MemBlockDevice {
+        type Error = OutOfBounds;
+
+        fn read_block(
+            &mut self,
+            addr: u32,
+            block: &mut [u8; SD_BLOCK_SIZE],
+        ) -> Result<(), Self::Error> {
+            *block = *self.blocks.get(addr as usize).ok_or(OutOfBounds(addr))?;
+            Ok(())
+        }
+
+        fn write_block(
+            &mut self,
+            addr: u32,
+            block: &[u8; SD_BLOCK_SIZE],
+        ) -> Result<(), Self::Error> {
+            *self
+                .blocks
+                .get_mut(addr as usize)
+                .ok_or(OutOfBounds(addr))? = *block;
+            Ok(())
+        }
+
+        fn block_count(&self) -> u32 {
+            self.blocks.len() as u32
+        }
+    }
+
+    /// A block device backed by a raw card image, or the card device itself.
+    pub struct FileBlockDevice {
+        file: File,
+        block_count: u32,
+    }
+
+    impl FileBlockDevice {
+        pub fn new(mut file: File) -> io::Result<Self> {
+            // the length of block devices is only available by seeking to the end
+            let len = file.seek(SeekFrom::End(0))?;
+            Ok(FileBlockDevice {
+                file,
+                block_count: (len / SD_BLOCK_SIZE as u64) as u32,
+            })
+        }
+    }
+
+    impl BlockDevice for FileBlockDevice {
+        type Error = io::Error;
+
+        fn read_block(
+            &mut self,
+            addr: u32,
+            block: &mut [u8; SD_BLOCK_SIZE],
+        ) -> Result<(), Self::Error> {
+            self.file
+                .seek(SeekFrom::Start(addr as u64 * SD_BLOCK_SIZE as u64))?;
+            self.file.read_exact(block)
+        }
+
+        fn write_block(
+            &mut self,
+            addr: u32,
+            block: &[u8; SD_BLOCK_SIZE],
+        ) -> Result<(), Self::Error> {
+            self.file
+                .seek(SeekFrom::Start(addr as u64 * SD_BLOCK_SIZE as u64))?;
+            self.file.write_all(block)?;
+
+            if addr >= self.block_count {
+                self.block_count = addr + 1;
+            }
+            Ok(())
+        }
+
+        fn block_count(&self) -> u32 {
+            self.block_count
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_header_size() {
+        let header = SettingsHeader {
+            magic: SETTINGS_MAGIC,
+            version: u16::MAX,
+            seq: u32::MAX,
+            len: u16::MAX,
+        };
+        assert_eq!(
+            postcard::to_stdvec(&header).unwrap().len(),
+            SETTINGS_HEADER_SIZE
+        );
+    }
+
+    #[test]
+    fn blank_device_has_default_settings() {
+        let mut dev = MemBlockDevice::new(16);
+        assert_eq!(
+            load_settings(&mut dev).unwrap(),
+            (Settings::default(), SettingsSource::Default)
+        );
+    }
+
+    #[test]
+    fn settings_alternate_between_blocks() {
+        let mut dev = MemBlockDevice::new(16);
+        let mut source = SettingsSource::Default;
+
+        for seq in 0..4 {
+            source = store_settings(&mut dev, &Settings::default(), source).unwrap();
+            let block = SETTINGS_BLOCKS[seq as usize % 2];
+            assert_eq!(source, SettingsSource::Stored { block, seq });
+            assert_eq!(load_settings(&mut dev).unwrap().1, source);
+        }
+    }
+
+    #[test]
+    fn damaged_copy_falls_back_to_other_block() {
+        let mut dev = MemBlockDevice::new(16);
+        let first =
+            store_settings(&mut dev, &Settings::default(), SettingsSource::Default).unwrap();
+        store_settings(&mut dev, &Settings::default(), first).unwrap();
+
+        // an interrupted write of the newest copy
+        dev.blocks_mut()[SETTINGS_BLOCKS[1] as usize][5] ^= 0x01;
+        assert_eq!(load_settings(&mut dev).unwrap().1, first);
+
+        // the next write replaces the damaged copy, not the valid one
+        let next = store_settings(&mut dev, &Settings::default(), first).unwrap();
+        assert_eq!(
+            next,
+            SettingsSource::Stored {
+                block: SETTINGS_BLOCKS[1],
+                seq: 1
+            }
+        );
+
+        // with both copies damaged the defaults are used
+        dev.blocks_mut()[SETTINGS_BLOCKS[0] as usize][0] ^= 0x01;
+        dev.blocks_mut()[SETTINGS_BLOCKS[1] as usize][SETTINGS_HEADER_SIZE] ^= 0x80;
+        assert_eq!(load_settings(&mut dev).unwrap().1, SettingsSource::Default);
+    }
+
+    #[test]
+    fn mem_device_bounds() {
+        let mut dev = MemBlockDevice::new(2);
+        let mut block = [0u8; SD_BLOCK_SIZE];
+        assert_eq!(dev.block_count(), 2);
+        assert!(dev.read_block(1, &mut block).is_ok());
+        assert!(dev.read_block(2, &mut block).is_err());
+        assert!(dev.write_block(2, &block).is_err());
+    }
+
+    #[test]
+    fn file_device_roundtrip() {
+        let path = std::env::temp_dir().join(format!("dsaclk-core-{}.img", std::process::id()));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+
+        let mut dev = FileBlockDevice::new(file).unwrap();
+        assert_eq!(dev.block_count(), 0);
+
+        let mut block = [0u8; SD_BLOCK_SIZE];
+        block[..3].copy_from_slice(b"abc");
+        dev.write_block(3, &block).unwrap();
+        assert_eq!(dev.block_count(), 4);
+
+        let mut read = [0xffu8; SD_BLOCK_SIZE];
+        dev.read_block(3, &mut read).unwrap();
+        assert_eq!(&read[..], &block[..]);
+
+        // the blocks before the written one read back as zeroes
+        dev.read_block(0, &mut read).unwrap();
+        assert!(read.iter().all(|&b| b == 0));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Writes `data` to the start of an otherwise zeroed block.
+    fn write_fixture(dev: &mut MemBlockDevice, addr: u32, data: &[u8]) {
+        let mut block = [0u8; SD_BLOCK_SIZE];
+        block[..data.len()].copy_from_slice(data);
+        dev.blocks_mut()[addr as usize] = block;
+    }
+
+    #[test]
+    fn loads_v0_fixture() {
+        // `logger_block: 1529`, written without a header before the settings were versioned
+        let mut dev = MemBlockDevice::new(16);
+        write_fixture(&mut dev, SETTINGS_BLOCK_IDX, &[0xf9, 0x05, 0x00, 0x00]);
+
+        assert_eq!(
+            load_settings(&mut dev).unwrap(),
+            (settings::decode(1, &[]).unwrap(), SettingsSource::Legacy)
+        );
+
+        // the next copy is written with a header
+        let source =
+            store_settings(&mut dev, &Settings::default(), SettingsSource::Legacy).unwrap();
+        assert_eq!(load_settings(&mut dev).unwrap().1, source);
+    }
+
+    #[test]
+    fn loads_v1_fixture() {
+        // empty settings with sequence number 7, from before the user preferences were stored
+        let mut dev = MemBlockDevice::new(16);
+        write_fixture(
+            &mut dev,
+            SETTINGS_BLOCKS[0],
+            &[
+                0x44, 0x53, 0x41, 0x53, 0x01, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x99, 0x3c,
+                0x0a, 0x9c,
+            ],
+        );
+
+        assert_eq!(
+            load_settings(&mut dev).unwrap(),
+            (
+                settings::decode(1, &[]).unwrap(),
+                SettingsSource::Stored {
+                    block: SETTINGS_BLOCKS[0],
+                    seq: 7
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn skips_copy_of_unknown_version() {
+        let mut dev = MemBlockDevice::new(16);
+        let first =
+            store_settings(&mut dev, &Settings::default(), SettingsSource::Default).unwrap();
+        store_settings(&mut dev, &Settings::default(), first).unwrap();
+
+        // a newer copy written by a later version, with a valid CRC
+        let mut block = dev.blocks_mut()[SETTINGS_BLOCKS[1] as usize];
+        block[4..6].copy_from_slice(&(SETTINGS_VERSION + 1).to_le_bytes());
+        let end = SETTINGS_HEADER_SIZE + u16::from_le_bytes([block[10], block[11]]) as usize;
+        let crc = SETTINGS_CRC.checksum(&block[..end]);
+        block[end..end + SETTINGS_CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+        dev.blocks_mut()[SETTINGS_BLOCKS[1] as usize] = block;
+
+        assert_eq!(load_settings(&mut dev).unwrap().1, first);
+    }
+
+    #[test]
+    fn writer_waits_until_settings_are_unchanged() {
+        let mut dev = MemBlockDevice::new(16);
+        let (settings, source) = load_settings(&mut dev).unwrap();
+        let mut writer = SettingsWriter::new(settings, source, 3);
+
+        // nothing is written while the settings are unchanged
+        for _ in 0..5 {
+            assert!(!writer.tick(&mut dev).unwrap());
+        }
+
+        // stepping through values keeps restarting the delay
+        for brightness in 65..70 {
+            writer.update(Settings {
+                backlight_brightness: brightness,
+                ..*writer.settings()
+            });
+            assert!(!writer.tick(&mut dev).unwrap());
+        }
+        assert_eq!(load_settings(&mut dev).unwrap().1, SettingsSource::Default);
+
+        assert!(!writer.tick(&mut dev).unwrap());
+        assert!(writer.tick(&mut dev).unwrap());
+        assert!(!writer.is_pending());
+        assert!(!writer.tick(&mut dev).unwrap());
+
+        let (loaded, source) = load_settings(&mut dev).unwrap();
+        assert_eq!(loaded.backlight_brightness, 69);
+        assert_eq!(source, writer.source());
+    }
+
+    #[test]
+    fn writer_waits_again_after_failed_write() {
+        // too small to hold the settings
+        let mut dev = MemBlockDevice::new(1);
+        let mut writer = SettingsWriter::new(Settings::default(), SettingsSource::Default, 3);
+        writer.update(Settings {
+            backlight_brightness: 0,
+            ..Settings::default()
+        });
+
+        let mut failures = 0;
+        for _ in 0..30 {
+            if writer.tick(&mut dev).is_err() {
+                failures += 1;
+            }
+        }
+        assert_eq!(failures, 10);
+        assert!(writer.is_pending());
+        assert_eq!(writer.source(), SettingsSource::Default);
+    }
+}
@@ -0,0 +1,400 @@
+//! Instants as seconds since 2000-01-01 00:00:00 and the durations between them.
+//!
+//! `ClockState` and `LogTimestamp` hold the date and time field by field like the RTC does, which
+//! is what is shown and logged. Converting them to a `Timestamp` gives a linear count of seconds
+//! that can be compared and subtracted across day, month and year boundaries.
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+};
+
+use crate::{
+    clock::{self, ClockState},
+    logger::LogTimestamp,
+};
+
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Days from 1970-01-01 to 2000-01-01.
+const DAYS_1970_TO_2000: i64 = 10957;
+
+/// Days since 2000-01-01 of a date of the proleptic Gregorian calendar. Does not panic on invalid
+/// dates, a day past the end of the month just counts into the next one.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html>.
+pub(crate) fn days_from_date(year: i64, month: u8, day: u8) -> i64 {
+    let (month, day) = (month as i64, day as i64);
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468 - DAYS_1970_TO_2000
+}
+
+/// The year, month and day of the day counted from 2000-01-01, the inverse of `days_from_date`.
+fn date_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + DAYS_1970_TO_2000 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u8, day as u8)
+}
+
+/// An instant, counted in seconds since 2000-01-01 00:00:00.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(i64);
+
+/// A signed number of seconds between two `Timestamp`s.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration(i64);
+
+impl Timestamp {
+    /// 2000-01-01 00:00:00, the earliest date the RTC can hold.
+    pub const EPOCH: Timestamp = Timestamp(0);
+
+    pub const fn from_seconds(seconds: i64) -> Self {
+        Timestamp(seconds)
+    }
+
+    /// Seconds since `EPOCH`.
+    pub const fn seconds(self) -> i64 {
+        self.0
+    }
+
+    /// The date and time, with `year` counted from 2000.
+    pub fn from_date_time(year: u8, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Self {
+        Timestamp(
+            days_from_date(2000 + year as i64, month, day) * SECONDS_PER_DAY
+                + hour as i64 * 3600
+                + minute as i64 * 60
+                + second as i64,
+        )
+    }
+
+    /// Time since the start of the day.
+    pub fn time_of_day(self) -> Duration {
+        Duration(self.0.rem_euclid(SECONDS_PER_DAY))
+    }
+
+    /// Start of the day of the instant.
+    pub fn midnight(self) -> Timestamp {
+        Timestamp(self.0 - self.0.rem_euclid(SECONDS_PER_DAY))
+    }
+
+    /// The full year, month, day, hour, minute and second.
+    fn fields(self) -> (i64, u8, u8, u8, u8, u8) {
+        let (year, month, day) = date_from_days(self.0.div_euclid(SECONDS_PER_DAY));
+        let time = self.0.rem_euclid(SECONDS_PER_DAY);
+        (
+            year,
+            month,
+            day,
+            (time / 3600) as u8,
+            (time / 60 % 60) as u8,
+            (time % 60) as u8,
+        )
+    }
+}
+
+impl Duration {
+    pub const ZERO: Duration = Duration(0);
+
+    pub const fn from_seconds(seconds: i64) -> Self {
+        Duration(seconds)
+    }
+
+    pub const fn from_minutes(minutes: i64) -> Self {
+        Duration(minutes * 60)
+    }
+
+    pub const fn from_hours(hours: i64) -> Self {
+        Duration(hours * 3600)
+    }
+
+    pub const fn from_days(days: i64) -> Self {
+        Duration(days * SECONDS_PER_DAY)
+    }
+
+    pub const fn seconds(self) -> i64 {
+        self.0
+    }
+
+    /// Whole minutes, rounded towards zero.
+    pub const fn minutes(self) -> i64 {
+        self.0 / 60
+    }
+
+    /// Whole hours, rounded towards zero.
+    pub const fn hours(self) -> i64 {
+        self.0 / 3600
+    }
+
+    pub const fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub const fn abs(self) -> Self {
+        Duration(self.0.abs())
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, d: Duration) -> Timestamp {
+        Timestamp(self.0 + d.0)
+    }
+}
+
+impl AddAssign<Duration> for Timestamp {
+    fn add_assign(&mut self, d: Duration) {
+        self.0 += d.0;
+    }
+}
+
+impl Sub<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn sub(self, d: Duration) -> Timestamp {
+        Timestamp(self.0 - d.0)
+    }
+}
+
+impl SubAssign<Duration> for Timestamp {
+    fn sub_assign(&mut self, d: Duration) {
+        self.0 -= d.0;
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = Duration;
+
+    fn sub(self, other: Timestamp) -> Duration {
+        Duration(self.0 - other.0)
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, other: Duration) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, other: Duration) -> Duration {
+        Duration(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, other: Duration) {
+        self.0 -= other.0;
+    }
+}
+
+impl Neg for Duration {
+    type Output = Duration;
+
+    fn neg(self) -> Duration {
+        Duration(-self.0)
+    }
+}
+
+impl From<&ClockState> for Timestamp {
+    fn from(s: &ClockState) -> Self {
+        Timestamp::from_date_time(s.year, s.month, s.day, s.hour, s.minute, s.second)
+    }
+}
+
+impl From<ClockState> for Timestamp {
+    fn from(s: ClockState) -> Self {
+        Timestamp::from(&s)
+    }
+}
+
+impl From<&LogTimestamp> for Timestamp {
+    fn from(t: &LogTimestamp) -> Self {
+        Timestamp::from_date_time(t.year, t.month, t.day, t.hour, t.minute, t.second)
+    }
+}
+
+/// Instants outside of the years 2000-2099 wrap around the century, like the RTC does.
+impl From<Timestamp> for ClockState {
+    fn from(t: Timestamp) -> Self {
+        let (year, month, day, hour, minute, second) = t.fields();
+        let year = year.rem_euclid(100) as u8;
+        ClockState {
+            hour,
+            minute,
+            second,
+            weekday: clock::weekday(day, month, year),
+            day,
+            month,
+            year,
+        }
+    }
+}
+
+/// Instants outside of the years 2000-2099 wrap around the century, like the RTC does.
+impl From<Timestamp> for LogTimestamp {
+    fn from(t: Timestamp) -> Self {
+        let (year, month, day, hour, minute, second) = t.fields();
+        LogTimestamp {
+            hour,
+            minute,
+            second,
+            day,
+            month,
+            year: year.rem_euclid(100) as u8,
+        }
+    }
+}
+
+/// Formats the instant as ISO 8601, like "2021-09-20T23:59:07".
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day, hour, minute, second) = self.fields();
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year, month, day, hour, minute, second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(year: u8, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Timestamp {
+        Timestamp::from_date_time(year, month, day, hour, minute, second)
+    }
+
+    #[test]
+    fn epoch_is_start_of_century() {
+        assert_eq!(ts(0, 1, 1, 0, 0, 0), Timestamp::EPOCH);
+        assert_eq!(Timestamp::EPOCH.to_string(), "2000-01-01T00:00:00");
+        // 2000 is a leap year
+        assert_eq!(ts(1, 1, 1, 0, 0, 0).seconds(), 366 * 86400);
+        assert_eq!(
+            Timestamp::from_seconds(366 * 86400).to_string(),
+            "2001-01-01T00:00:00"
+        );
+    }
+
+    #[test]
+    fn crosses_day_month_and_year_boundaries() {
+        let t = ts(23, 12, 31, 23, 59, 59);
+        assert_eq!(
+            (t + Duration::from_seconds(1)).to_string(),
+            "2024-01-01T00:00:00"
+        );
+        assert_eq!(
+            (t + Duration::from_days(31) + Duration::from_seconds(1)).to_string(),
+            "2024-02-01T00:00:00"
+        );
+        assert_eq!(
+            ts(21, 10, 1, 0, 0, 0) - ts(21, 9, 30, 23, 0, 0),
+            Duration::from_hours(1)
+        );
+    }
+
+    #[test]
+    fn crosses_leap_days() {
+        let t = ts(24, 2, 28, 23, 59, 59);
+        assert_eq!(
+            (t + Duration::from_seconds(1)).to_string(),
+            "2024-02-29T00:00:00"
+        );
+        assert_eq!(
+            (t + Duration::from_seconds(86401)).to_string(),
+            "2024-03-01T00:00:00"
+        );
+        assert_eq!(
+            ts(24, 3, 1, 0, 0, 0) - ts(24, 2, 1, 0, 0, 0),
+            Duration::from_days(29)
+        );
+        assert_eq!(
+            ts(23, 3, 1, 0, 0, 0) - ts(23, 2, 1, 0, 0, 0),
+            Duration::from_days(28)
+        );
+        assert_eq!(
+            ts(25, 1, 1, 0, 0, 0) - ts(24, 1, 1, 0, 0, 0),
+            Duration::from_days(366)
+        );
+    }
+
+    #[test]
+    fn roundtrips_through_clock_state_and_log_timestamp() {
+        let c = ClockState {
+            hour: 23,
+            minute: 59,
+            second: 7,
+            weekday: 1,
+            day: 20,
+            month: 9,
+            year: 21,
+        };
+        let t = Timestamp::from(c);
+        assert_eq!(ClockState::from(t), c);
+        assert_eq!(t.to_string(), "2021-09-20T23:59:07");
+
+        // the weekday is computed for the new date
+        let next = ClockState::from(t + Duration::from_minutes(1));
+        assert_eq!((next.day, next.weekday, next.hour), (21, 2, 0));
+
+        let l = LogTimestamp::from(&c);
+        assert_eq!(Timestamp::from(&l), t);
+        let back = LogTimestamp::from(t + Duration::from_days(365));
+        assert_eq!((back.year, back.month, back.day), (22, 9, 20));
+    }
+
+    #[test]
+    fn compares_and_measures_durations() {
+        let bed = ts(21, 9, 20, 23, 15, 0);
+        let alarm = ts(21, 9, 21, 6, 45, 0);
+        assert!(bed < alarm);
+
+        let sleep = alarm - bed;
+        assert_eq!(sleep, Duration::from_hours(7) + Duration::from_minutes(30));
+        assert_eq!((sleep.hours(), sleep.minutes()), (7, 450));
+        assert_eq!(bed - alarm, -sleep);
+        assert!((bed - alarm).is_negative());
+        assert_eq!((bed - alarm).abs(), sleep);
+
+        let mut t = bed;
+        t += sleep;
+        assert_eq!(t, alarm);
+        t -= Duration::from_days(1);
+        assert_eq!(t.midnight(), ts(21, 9, 20, 0, 0, 0));
+        assert_eq!(t.time_of_day(), Duration::from_seconds(6 * 3600 + 45 * 60));
+    }
+
+    #[test]
+    fn wraps_around_the_century() {
+        let t = ts(99, 12, 31, 23, 59, 59) + Duration::from_seconds(1);
+        assert_eq!(t.to_string(), "2100-01-01T00:00:00");
+        let c = ClockState::from(t);
+        assert_eq!((c.year, c.month, c.day), (0, 1, 1));
+
+        let before = Timestamp::from_seconds(-1);
+        assert_eq!(before.to_string(), "1999-12-31T23:59:59");
+        assert_eq!(before.time_of_day(), Duration::from_seconds(86399));
+    }
+}
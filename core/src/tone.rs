@@ -0,0 +1,292 @@
+//! Melodies of notes and rests, played without blocking by a `Sequencer` that is advanced from a
+//! timer interrupt or the main loop.
+use heapless::Vec;
+
+use crate::{rtttl, settings::AlarmTone};
+
+/// A tone of a frequency in Hz, or a rest if it is `None`, lasting `duration` milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Note {
+    pub freq: Option<u16>,
+    pub duration: u16,
+}
+
+impl Note {
+    pub const fn tone(freq: u16, duration: u16) -> Self {
+        Note {
+            freq: Some(freq),
+            duration,
+        }
+    }
+
+    pub const fn rest(duration: u16) -> Self {
+        Note {
+            freq: None,
+            duration,
+        }
+    }
+}
+
+/// Where the `Sequencer` plays the notes, like the buzzer of the `Player`.
+pub trait ToneOutput {
+    /// Plays a tone of the frequency in Hz, replacing any playing one.
+    fn start_tone(&mut self, freq: u16);
+    fn stop_tone(&mut self);
+}
+
+/// Maximum number of notes a `Sequencer` holds.
+pub const MAX_NOTES: usize = 64;
+
+/// Plays a list of notes on a `ToneOutput`, one note after another as time passes in `tick`.
+pub struct Sequencer {
+    notes: Vec<Note, MAX_NOTES>,
+    /// Index of the playing note and the milliseconds it still lasts, `None` when stopped.
+    position: Option<(usize, u32)>,
+    /// Starts over after the last note.
+    repeat: bool,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Sequencer {
+            notes: Vec::new(),
+            position: None,
+            repeat: false,
+        }
+    }
+
+    /// Starts playing the notes from the first one, over and over again if `repeat`. Notes beyond
+    /// `MAX_NOTES` are left out, and notes without any duration are not played at all.
+    pub fn start(&mut self, notes: &[Note], repeat: bool, out: &mut impl ToneOutput) {
+        self.notes.clear();
+        let count = notes.len().min(MAX_NOTES);
+        let _ = self.notes.extend_from_slice(&notes[..count]);
+        self.repeat = repeat;
+
+        match self.notes.iter().position(|n| n.duration > 0) {
+            Some(first) => {
+                self.position = Some((first, self.notes[first].duration as u32));
+                self.play(first, out);
+            }
+            None => self.stop(out),
+        }
+    }
+
+    pub fn stop(&mut self, out: &mut impl ToneOutput) {
+        self.position = None;
+        out.stop_tone();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.position.is_some()
+    }
+
+    /// Advances the melody by `elapsed` milliseconds, playing the note that is due then.
+    pub fn tick(&mut self, elapsed: u32, out: &mut impl ToneOutput) {
+        let (mut index, mut left) = match self.position {
+            Some(position) => position,
+            None => return,
+        };
+
+        let mut elapsed = elapsed;
+        let mut changed = false;
+        while elapsed >= left {
+            elapsed -= left;
+            index += 1;
+            if index == self.notes.len() {
+                if !self.repeat {
+                    self.stop(out);
+                    return;
+                }
+                index = 0;
+            }
+            left = self.notes[index].duration as u32;
+            changed = true;
+        }
+
+        self.position = Some((index, left - elapsed));
+        if changed {
+            self.play(index, out);
+        }
+    }
+
+    fn play(&self, index: usize, out: &mut impl ToneOutput) {
+        match self.notes[index].freq {
+            Some(freq) => out.start_tone(freq),
+            None => out.stop_tone(),
+        }
+    }
+}
+
+/// The notes of an `AlarmTone`, played over and over while an alarm rings.
+pub fn melody(tone: AlarmTone) -> Vec<Note, MAX_NOTES> {
+    match tone {
+        AlarmTone::Beep => Vec::from_slice(&BEEP).ok(),
+        AlarmTone::Chirp => Vec::from_slice(&CHIRP).ok(),
+        AlarmTone::Siren => Vec::from_slice(&SIREN).ok(),
+        AlarmTone::OdeToJoy => rtttl::parse(ODE_TO_JOY).ok(),
+        AlarmTone::FurElise => rtttl::parse(FUR_ELISE).ok(),
+        AlarmTone::MorningMood => rtttl::parse(MORNING_MOOD).ok(),
+    }
+    // the built-in melodies are all tested to be valid
+    .unwrap_or_default()
+}
+
+const ODE_TO_JOY: &str = "Ode to Joy:d=4,o=5,b=140:\
+    e,e,f,g,g,f,e,d,c,c,d,e,e.,8d,2d,e,e,f,g,g,f,e,d,c,c,d,e,d.,8c,2c,2p";
+
+const FUR_ELISE: &str = "Fur Elise:d=16,o=5,b=125:\
+    e6,d#6,e6,d#6,e6,b,d6,c6,8a,p,c,e,a,8b,p,e,g#,b,8c6,p,e,\
+    e6,d#6,e6,d#6,e6,b,d6,c6,8a,p,c,e,a,8b,p,e,c6,b,4a,4p";
+
+const MORNING_MOOD: &str = "Morning Mood:d=8,o=6,b=90:\
+    g,e,d,c,d,e,g,e,d,c,d,e,16d,16e,g,e,g,a,e,a,g,e,d,4c.,4p";
+
+const BEEP: [Note; 6] = [
+    Note::tone(2000, 200),
+    Note::rest(200),
+    Note::tone(2000, 200),
+    Note::rest(200),
+    Note::tone(2000, 200),
+    Note::rest(200),
+];
+
+/// Three sweeps from 1 to 3 kHz in steps of 250 Hz, each followed by a short rest.
+const CHIRP: [Note; 30] = {
+    let mut notes = [Note::rest(100); 30];
+    let mut i = 0;
+    while i < 30 {
+        if i % 10 != 9 {
+            notes[i] = Note::tone(1000 + 250 * (i % 10) as u16, 40);
+        }
+        i += 1;
+    }
+    notes
+};
+
+const SIREN: [Note; 2] = [Note::tone(600, 250), Note::tone(900, 250)];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records what is played, and when.
+    #[derive(Default)]
+    struct FakeOutput {
+        now: u32,
+        played: std::vec::Vec<(u32, Option<u16>)>,
+    }
+
+    impl ToneOutput for FakeOutput {
+        fn start_tone(&mut self, freq: u16) {
+            self.played.push((self.now, Some(freq)));
+        }
+
+        fn stop_tone(&mut self) {
+            self.played.push((self.now, None));
+        }
+    }
+
+    fn run(sequencer: &mut Sequencer, out: &mut FakeOutput, ms: u32, step: u32) {
+        for _ in 0..ms / step {
+            out.now += step;
+            sequencer.tick(step, out);
+        }
+    }
+
+    #[test]
+    fn plays_notes_and_rests_in_time() {
+        let mut sequencer = Sequencer::new();
+        let mut out = FakeOutput::default();
+        let notes = [Note::tone(440, 100), Note::rest(50), Note::tone(880, 30)];
+
+        sequencer.start(&notes, false, &mut out);
+        assert!(sequencer.is_playing());
+        run(&mut sequencer, &mut out, 300, 10);
+        assert!(!sequencer.is_playing());
+        assert_eq!(
+            out.played,
+            [(0, Some(440)), (100, None), (150, Some(880)), (180, None)]
+        );
+    }
+
+    #[test]
+    fn carries_over_coarse_ticks() {
+        let mut sequencer = Sequencer::new();
+        let mut out = FakeOutput::default();
+        let notes = [Note::tone(440, 40), Note::tone(880, 40), Note::rest(100)];
+
+        // a tick longer than a note skips it, but the timing of the following ones is kept
+        sequencer.start(&notes, true, &mut out);
+        run(&mut sequencer, &mut out, 300, 100);
+        assert_eq!(
+            out.played,
+            [(0, Some(440)), (100, None), (200, Some(440)), (300, None)]
+        );
+        assert!(sequencer.is_playing());
+    }
+
+    #[test]
+    fn repeats_until_stopped() {
+        let mut sequencer = Sequencer::new();
+        let mut out = FakeOutput::default();
+
+        sequencer.start(&melody(AlarmTone::Siren), true, &mut out);
+        run(&mut sequencer, &mut out, 1000, 50);
+        assert_eq!(
+            out.played,
+            [
+                (0, Some(600)),
+                (250, Some(900)),
+                (500, Some(600)),
+                (750, Some(900)),
+                (1000, Some(600))
+            ]
+        );
+
+        sequencer.stop(&mut out);
+        assert!(!sequencer.is_playing());
+        assert_eq!(out.played.last(), Some(&(1000, None)));
+        run(&mut sequencer, &mut out, 1000, 50);
+        assert_eq!(out.played.len(), 6);
+    }
+
+    #[test]
+    fn skips_notes_without_duration() {
+        let mut sequencer = Sequencer::new();
+        let mut out = FakeOutput::default();
+
+        sequencer.start(&[Note::tone(440, 0), Note::tone(880, 10)], false, &mut out);
+        assert_eq!(out.played, [(0, Some(880))]);
+
+        sequencer.start(&[Note::tone(440, 0)], true, &mut out);
+        assert!(!sequencer.is_playing());
+    }
+
+    #[test]
+    fn melodies_last_as_long_as_before() {
+        let total = |tone| -> u32 { melody(tone).iter().map(|n| n.duration as u32).sum() };
+        assert_eq!(total(AlarmTone::Beep), 1200);
+        assert_eq!(total(AlarmTone::Chirp), 3 * (9 * 40 + 100));
+        assert_eq!(total(AlarmTone::Siren), 500);
+        assert_eq!(melody(AlarmTone::Chirp)[8], Note::tone(3000, 40));
+    }
+
+    #[test]
+    fn built_in_ringtones_are_valid() {
+        for text in [ODE_TO_JOY, FUR_ELISE, MORNING_MOOD] {
+            assert!(rtttl::parse(text).is_ok(), "{}", text);
+        }
+        for tone in AlarmTone::ALL {
+            assert!(!melody(tone).is_empty());
+        }
+        assert_eq!(melody(AlarmTone::OdeToJoy)[0], Note::tone(659, 428));
+    }
+}
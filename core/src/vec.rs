@@ -55,8 +55,27 @@ impl DivAssign<f32> for Vec3f {
     }
 }
 
+#[cfg(feature = "nalgebra")]
 impl From<nalgebra::Vector3<f32>> for Vec3f {
     fn from(d: nalgebra::Vector3<f32>) -> Self {
         Vec3f(d.x, d.y, d.z)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let mut v = Vec3f(1.0, 2.0, 3.0) + Vec3f(1.0, 1.0, 1.0);
+        assert_eq!((v.0, v.1, v.2), (2.0, 3.0, 4.0));
+
+        v -= Vec3f(0.0, 1.0, 2.0);
+        assert_eq!((v.0, v.1, v.2), (2.0, 2.0, 2.0));
+
+        v /= 2.0;
+        assert_eq!((v.0, v.1, v.2), (1.0, 1.0, 1.0));
+        assert_eq!(v.len2(), 3.0);
+    }
+}
@@ -0,0 +1,218 @@
+//! Waking up in light sleep within the wake window of an alarm.
+//!
+//! A sleeper moves around more in light sleep than in deep sleep, so a few movements of the bed
+//! within a short period are taken as a sign of light sleep. Movement is detected from the same
+//! signals the actigraphy of the tools counts: the rotation measured by the gyroscope and the
+//! change in mean acceleration between consecutive measurements.
+use heapless::Deque;
+
+use crate::{
+    alarm::Alarm,
+    mpu::Measurement,
+    time::{Duration, Timestamp},
+    vec::Vec3f,
+};
+
+/// Gyroscope magnitude (rad/s) above which a measurement counts as movement.
+pub const GYRO_THRESHOLD: f32 = 0.05;
+/// Change in mean acceleration (g) since the previous measurement above which a measurement
+/// counts as movement.
+pub const ACC_THRESHOLD: f32 = 0.02;
+/// Number of movements within `MOVEMENT_PERIOD` that suggest light sleep.
+pub const MOVEMENT_COUNT: usize = 3;
+pub const MOVEMENT_PERIOD: Duration = Duration::from_minutes(2);
+
+/// Watches the measurements for light sleep while the next alarm is in its wake window.
+pub struct SmartWake {
+    last_acc: Option<Vec3f>,
+    /// Times of the last movements, the oldest first.
+    movements: Deque<Timestamp, MOVEMENT_COUNT>,
+    /// The alarm and set time of the occurrence that already went off early.
+    woken: Option<(usize, Timestamp)>,
+}
+
+impl Default for SmartWake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SmartWake {
+    pub fn new() -> Self {
+        SmartWake {
+            last_acc: None,
+            movements: Deque::new(),
+            woken: None,
+        }
+    }
+
+    /// Records a measurement taken at `now`. Returns the index of the next alarm, as returned by
+    /// `Scheduler::next`, if it should go off early because of light sleep in its wake window.
+    pub fn record(
+        &mut self,
+        m: &Measurement,
+        now: Timestamp,
+        next: Option<(usize, Timestamp)>,
+        alarms: &[Alarm],
+    ) -> Option<usize> {
+        if self.is_movement(m) {
+            if self.movements.is_full() {
+                self.movements.pop_front();
+            }
+            let _ = self.movements.push_back(now);
+        }
+
+        let (alarm, at) = next?;
+        let start = at - alarms[alarm].window();
+        if start >= at || now < start || now >= at || self.is_woken(alarm, at) {
+            return None;
+        }
+
+        // only the movements within the window count
+        let since = if now - MOVEMENT_PERIOD > start {
+            now - MOVEMENT_PERIOD
+        } else {
+            start
+        };
+        match self.movements.front() {
+            Some(&first) if self.movements.is_full() && first >= since => {
+                self.woken = Some((alarm, at));
+                self.movements.clear();
+                Some(alarm)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the occurrence of the alarm at `at` already went off early.
+    pub fn is_woken(&self, alarm: usize, at: Timestamp) -> bool {
+        self.woken == Some((alarm, at))
+    }
+
+    /// Returns `true` if the alarm that went off at `now` was already woken up for early, which it
+    /// then is no longer.
+    pub fn take_woken(&mut self, alarm: usize, now: Timestamp) -> bool {
+        match self.woken {
+            Some((i, at)) if i == alarm && (now - at).abs() < Duration::from_minutes(1) => {
+                self.woken = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn is_movement(&mut self, m: &Measurement) -> bool {
+        let acc_change = match self.last_acc {
+            Some(last) => (m.acc_mean - last).len2(),
+            None => 0.0,
+        };
+        self.last_acc = Some(m.acc_mean);
+        m.gyro_mag_max > GYRO_THRESHOLD || acc_change > ACC_THRESHOLD * ACC_THRESHOLD
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        alarm::{Weekdays, MAX_VOLUME},
+        settings::AlarmTone,
+    };
+
+    fn at(hour: u8, minute: u8, second: u8) -> Timestamp {
+        Timestamp::from_date_time(21, 9, 20, hour, minute, second)
+    }
+
+    fn measurement(gyro: f32) -> Measurement {
+        Measurement {
+            acc_mean: Vec3f(0.0, 0.0, 1.0),
+            temp_mean: 20.0,
+            gyro_mag_max: gyro,
+        }
+    }
+
+    fn alarms(window: u8) -> [Alarm; 1] {
+        [Alarm {
+            hour: 7,
+            minute: 0,
+            days: Weekdays::EVERY_DAY,
+            enabled: true,
+            window,
+            tone: AlarmTone::Beep,
+            volume: MAX_VOLUME,
+        }]
+    }
+
+    #[test]
+    fn wakes_on_movement_in_window() {
+        let mut wake = SmartWake::new();
+        let alarms = alarms(30);
+        let next = Some((0, at(7, 0, 0)));
+
+        // movement before the window is ignored
+        for s in 0..3 {
+            let now = at(6, 29, 50 + s);
+            assert_eq!(wake.record(&measurement(0.5), now, next, &alarms), None);
+        }
+        assert_eq!(
+            wake.record(&measurement(0.0), at(6, 30, 0), next, &alarms),
+            None
+        );
+
+        // a single movement is not enough
+        let still = measurement(0.0);
+        assert_eq!(
+            wake.record(&measurement(0.5), at(6, 40, 0), next, &alarms),
+            None
+        );
+        assert_eq!(wake.record(&still, at(6, 41, 0), next, &alarms), None);
+        assert_eq!(
+            wake.record(&measurement(0.5), at(6, 43, 0), next, &alarms),
+            None
+        );
+        assert_eq!(
+            wake.record(&measurement(0.5), at(6, 44, 0), next, &alarms),
+            None
+        );
+        assert_eq!(
+            wake.record(&measurement(0.5), at(6, 44, 30), next, &alarms),
+            Some(0)
+        );
+
+        // but only once per occurrence
+        for s in 0..3 {
+            let now = at(6, 45, s);
+            assert_eq!(wake.record(&measurement(0.5), now, next, &alarms), None);
+        }
+        assert!(!wake.take_woken(0, at(6, 59, 0)));
+        assert!(wake.take_woken(0, at(7, 0, 0)));
+        assert!(!wake.take_woken(0, at(7, 0, 0)));
+    }
+
+    #[test]
+    fn acceleration_change_is_movement() {
+        let mut wake = SmartWake::new();
+        let alarms = alarms(30);
+        let next = Some((0, at(7, 0, 0)));
+
+        let mut m = measurement(0.0);
+        for s in 0..4 {
+            m.acc_mean = Vec3f(0.1 * (s % 2) as f32, 0.0, 1.0);
+            let woken = wake.record(&m, at(6, 50, s), next, &alarms);
+            assert_eq!(woken, if s == 3 { Some(0) } else { None });
+        }
+    }
+
+    #[test]
+    fn no_window_no_early_wake() {
+        let mut wake = SmartWake::new();
+        let alarms = alarms(0);
+        let next = Some((0, at(7, 0, 0)));
+
+        for s in 0..10 {
+            let now = at(6, 59, s);
+            assert_eq!(wake.record(&measurement(0.5), now, next, &alarms), None);
+        }
+        assert!(!wake.take_woken(0, at(7, 0, 0)));
+    }
+}
@@ -1,40 +1,11 @@
 use cortex_m::interrupt::free;
 use stm32f4xx_hal::{interrupt, stm32 as stm32f401};
 
-use crate::event::InterruptEvent;
-use crate::EVENT_QUEUE;
-
-#[derive(Debug, Clone, Copy)]
-pub struct ClockState {
-    pub hour: u8,
-    pub minute: u8,
-    pub second: u8,
-    pub weekday: u8,
-    pub day: u8,
-    pub month: u8,
-    pub year: u8,
-}
+use dsaclk_core::event::InterruptEvent;
 
-impl Default for ClockState {
-    fn default() -> Self {
-        ClockState {
-            hour: 0,
-            minute: 0,
-            second: 0,
-            weekday: 1,
-            day: 1,
-            month: 1,
-            year: 0,
-        }
-    }
-}
+use crate::EVENT_QUEUE;
 
-#[derive(Debug, Clone, Copy)]
-pub struct AlarmState {
-    pub hour: u8,
-    pub minute: u8,
-    pub enabled: bool,
-}
+pub use dsaclk_core::clock::{AlarmState, ClockState, Rtc};
 
 pub struct Clock {
     rtc: stm32f401::RTC,
@@ -94,7 +65,30 @@ impl Clock {
         self.rtc.isr.read().inits().bit_is_set()
     }
 
-    pub fn get_state(&self) -> ClockState {
+    pub fn alarm_triggered(&self) -> bool {
+        self.rtc.isr.read().alraf().is_match_()
+    }
+
+    pub fn enable_alarm_interrupt(&mut self, exti: &stm32f401::EXTI) {
+        // According to page 449 of the STM32F401xDE reference manual
+
+        // enable EXTI Line 17 in interrupt mode and select rising edge sensitivity
+        exti.imr.modify(|_, w| w.mr17().unmasked());
+        exti.rtsr.modify(|_, w| w.tr17().enabled());
+
+        // enable RTC alarm A interrupt
+        self.protected(|rtc| rtc.cr.modify(|_, w| w.alraie().enabled()));
+
+        // enable RTC Alarm interrupt in the NVIC
+        stm32f401::NVIC::unpend(stm32f4xx_hal::interrupt::RTC_ALARM);
+        unsafe {
+            stm32f401::NVIC::unmask(stm32f4xx_hal::interrupt::RTC_ALARM);
+        };
+    }
+}
+
+impl Rtc for Clock {
+    fn get_state(&self) -> ClockState {
         let tr = self.rtc.tr.read();
         let dr = self.rtc.dr.read();
 
@@ -109,7 +103,7 @@ impl Clock {
         }
     }
 
-    pub fn set_state(&mut self, state: ClockState) {
+    fn set_state(&mut self, state: ClockState) {
         self.initialization_mode(|rtc| {
             rtc.tr.modify(|_, w| {
                 w.ht()
@@ -126,7 +120,9 @@ impl Clock {
                     .bits(state.second % 10)
             });
             rtc.dr.modify(|_, w| {
-                w.dt()
+                w.wdu()
+                    .bits(state.weekday)
+                    .dt()
                     .bits(state.day / 10)
                     .du()
                     .bits(state.day % 10)
@@ -144,17 +140,22 @@ impl Clock {
         })
     }
 
-    pub fn get_alarm(&self) -> AlarmState {
+    fn get_alarm(&self) -> AlarmState {
         let cr = self.rtc.cr.read();
         let alrmar = self.rtc.alrmar.read();
         AlarmState {
             hour: alrmar.ht().bits() * 10 + alrmar.hu().bits(),
             minute: alrmar.mnt().bits() * 10 + alrmar.mnu().bits(),
+            // the weekday is compared if MSK4 is cleared and WDSEL is set
+            weekday: match alrmar.msk4().bit_is_clear() && alrmar.wdsel().bit_is_set() {
+                true => Some(alrmar.du().bits()),
+                false => None,
+            },
             enabled: cr.alrae().is_enabled(),
         }
     }
 
-    pub fn set_alarm(&mut self, alarm: AlarmState) {
+    fn set_alarm(&mut self, alarm: AlarmState) {
         self.protected(|rtc| {
             // disable alarm A
             rtc.cr.modify(|_, w| w.alrae().disabled());
@@ -171,7 +172,13 @@ impl Clock {
                     .msk3()
                     .mask() // care about hours
                     .msk4()
-                    .not_mask() // do not care about date/week day
+                    .bit(alarm.weekday.is_none()) // care about the week day, if there is one
+                    .wdsel()
+                    .set_bit() // match the week day instead of the date
+                    .dt()
+                    .bits(0)
+                    .du()
+                    .bits(alarm.weekday.unwrap_or(1)) // set week day
                     .pm()
                     .am() // AM/24 hour format
                     .ht()
@@ -198,30 +205,9 @@ impl Clock {
         });
     }
 
-    pub fn alarm_triggered(&self) -> bool {
-        self.rtc.isr.read().alraf().is_match_()
-    }
-
-    pub fn alarm_reset(&mut self) {
+    fn alarm_reset(&mut self) {
         self.rtc.isr.modify(|_, w| w.alraf().clear())
     }
-
-    pub fn enable_alarm_interrupt(&mut self, exti: &stm32f401::EXTI) {
-        // According to page 449 of the STM32F401xDE reference manual
-
-        // enable EXTI Line 17 in interrupt mode and select rising edge sensitivity
-        exti.imr.modify(|_, w| w.mr17().unmasked());
-        exti.rtsr.modify(|_, w| w.tr17().enabled());
-
-        // enable RTC alarm A interrupt
-        self.protected(|rtc| rtc.cr.modify(|_, w| w.alraie().enabled()));
-
-        // enable RTC Alarm interrupt in the NVIC
-        stm32f401::NVIC::unpend(stm32f4xx_hal::interrupt::RTC_ALARM);
-        unsafe {
-            stm32f401::NVIC::unmask(stm32f4xx_hal::interrupt::RTC_ALARM);
-        };
-    }
 }
 
 #[interrupt]
@@ -12,10 +12,9 @@ use hal::{
 };
 use stm32f4xx_hal as hal;
 
-pub enum Button {
-    ShortPress,
-    LongPress,
-}
+use dsaclk_core::button::ButtonState;
+
+pub use dsaclk_core::button::Button;
 
 pub type Pb4Af2 = PB4<Alternate<AF2>>;
 pub type Pb5Af2 = PB5<Alternate<AF2>>;
@@ -24,7 +23,7 @@ pub struct Encoder {
     qei: Qei<TIM3, (Pb4Af2, Pb5Af2)>,
     last_count: u16,
     btn_pin: PB14<Input<PullDown>>,
-    btn_count: u32,
+    btn_state: ButtonState,
 }
 
 impl Encoder {
@@ -35,7 +34,7 @@ impl Encoder {
             qei,
             last_count,
             btn_pin,
-            btn_count: 0,
+            btn_state: ButtonState::new(),
         }
     }
 
@@ -53,19 +52,6 @@ impl Encoder {
 
     pub fn check_btn(&mut self, timeout: u32) -> Option<Button> {
         let pressed = self.btn_pin.is_high().unwrap();
-
-        let evt = match (pressed, self.btn_count) {
-            (false, cnt) if 0 < cnt && cnt < timeout => Some(Button::ShortPress),
-            (true, cnt) if cnt < timeout => None,
-            (true, cnt) if cnt == timeout => Some(Button::LongPress),
-            (..) => None,
-        };
-
-        self.btn_count = match pressed {
-            true => self.btn_count.saturating_add(1), // avoid overflow
-            false => 0,
-        };
-
-        evt
+        self.btn_state.update(pressed, timeout)
     }
 }
@@ -3,22 +3,25 @@
 
 mod clock;
 mod defmt_uart;
-mod dialog;
-mod display;
 mod encoder;
-mod event;
-mod logger;
 mod mpu;
-mod panel;
 mod player;
 mod sdcard;
 mod util;
-mod vec;
 
-use clock::{Clock, ClockState};
+use clock::{Clock, Rtc};
+use core::sync::atomic::{AtomicU32, Ordering};
 use defmt::{debug, error, info};
+use dsaclk_core::{
+    alarm::{Alarm, Weekdays},
+    app::{App, LONG_PRESS_DURATION, POLL_FREQ},
+    display::{self, Display, I2CDisplayDriver},
+    event::{EventQueue, InterruptEvent},
+    logger::{LogContents, Logger},
+    panel::{self, CursorState, Panels},
+    settings, storage, tone, Diagnostics,
+};
 use encoder::Encoder;
-use event::{EventQueue, InterruptEvent};
 
 // use panic-probe as panicking behavior which will print panic
 // messages through defmt
@@ -41,27 +44,28 @@ use stm32f4xx_hal::{
 };
 use util::GlobalCell;
 
-use crate::{
-    clock::AlarmState,
-    display::{Display, I2CDisplayDriver},
-    logger::{LogContents, Logger},
-    mpu::MPU,
-};
-use crate::{dialog::Dialog, panel::CursorState};
-use crate::{panel::Panel, sdcard::SdCard};
-
-const POLL_FREQ: u32 = 10;
-const LONG_PRESS_DURATION: u32 = 2;
+use crate::{mpu::MPU, sdcard::SdCard};
 
 // global variables to be shared with ISRs
 static ENCODER: GlobalCell<Encoder> = GlobalCell::new(None);
 static TIMER_TIM5: GlobalCell<Timer<stm32::TIM5>> = GlobalCell::new(None);
+static TIMER_TIM2: GlobalCell<Timer<stm32::TIM2>> = GlobalCell::new(None);
+static PLAYER: GlobalCell<Player> = GlobalCell::new(None);
 
 /// UART port used for debug
 static DEBUG_UART_TX: GlobalCell<Tx<USART2>> = GlobalCell::new(None);
 
 static EVENT_QUEUE: EventQueue<32> = EventQueue::new();
 
+/// Ticks the button has to be held down to generate a long press, set from the settings.
+static LONG_PRESS_TICKS: AtomicU32 = AtomicU32::new(LONG_PRESS_DURATION * POLL_FREQ);
+
+/// Milliseconds between the interrupts of TIM2 that advance the melody of the `Player`.
+const PLAYER_TICK_MS: u32 = 5;
+
+/// Ticks the settings have to stay unchanged before they are written to the card.
+const SETTINGS_WRITE_DELAY: u32 = 5 * POLL_FREQ;
+
 /// Logs the formatted string to the debug USART port.
 /// Note: do _not_ use this inside a CriticalSection. Instead, use `logf_cs` and pass in the CriticalSection object
 #[allow(unused_macros)]
@@ -79,12 +83,6 @@ macro_rules! logf_cs {
     );
 }
 
-#[derive(Debug)]
-pub struct SharedState {
-    clock: ClockState,
-    alarm: AlarmState,
-}
-
 #[interrupt]
 fn TIM5() {
     free(|cs| {
@@ -104,7 +102,7 @@ fn TIM5() {
                 EVENT_QUEUE.put(cs, InterruptEvent::Encoder(change));
             }
 
-            if let Some(evt) = enc.check_btn(LONG_PRESS_DURATION * POLL_FREQ) {
+            if let Some(evt) = enc.check_btn(LONG_PRESS_TICKS.load(Ordering::Relaxed)) {
                 EVENT_QUEUE.put(
                     cs,
                     match evt {
@@ -118,6 +116,40 @@ fn TIM5() {
         });
     })
 }
+#[interrupt]
+fn TIM2() {
+    free(|cs| {
+        TIMER_TIM2.try_borrow_mut::<_, ()>(cs, |tim| {
+            tim.clear_interrupt(Event::TimeOut);
+            Some(())
+        });
+
+        PLAYER.try_borrow_mut(cs, |player| {
+            player.tick(PLAYER_TICK_MS);
+            Some(())
+        });
+    })
+}
+
+/// Keeps the diagnostics up to date after writing to the log. A failed write turns logging off, so
+/// that a full or broken card does not stop the clock, and the reason is shown on the diagnostics
+/// panel.
+fn check_log<E: core::fmt::Debug>(
+    logger: &mut Option<Logger>,
+    result: Result<(), storage::Error<E>>,
+    diagnostics: &mut Diagnostics,
+) {
+    match result {
+        Ok(()) => diagnostics.log_block = logger.as_ref().map(|l| l.next_block()),
+        Err(e) => {
+            error!("Logging disabled: {}", defmt::Debug2Format(&e));
+            *logger = None;
+            diagnostics.log_block = None;
+            diagnostics.log_error = Some(e.kind());
+        }
+    }
+}
+
 #[entry]
 fn main() -> ! {
     let peripherals = stm32f4xx_hal::stm32::Peripherals::take().unwrap();
@@ -174,11 +206,15 @@ fn main() -> ! {
 
     led.set_high().unwrap();
 
-    // experiment with tone generator
-
-    #[allow(unused_variables)]
-    let player = Player::new(peripherals.TIM4, gpiob.pb7.into_alternate_af2(), &clocks);
-    // player.play(&mut delay);
+    // tone generator for the alarm, its melodies are advanced by the TIM2 interrupt
+    PLAYER.put(Player::new(
+        peripherals.TIM4,
+        gpiob.pb7.into_alternate_af2(),
+        &clocks,
+    ));
+    let mut player_timer = Timer::tim2(peripherals.TIM2, (1000 / PLAYER_TICK_MS).hz(), clocks);
+    player_timer.listen(Event::TimeOut);
+    TIMER_TIM2.put(player_timer);
 
     // setup Timer 3 as an encoder and put it in the mutex as a global variable
     ENCODER.put(Encoder::new(
@@ -201,10 +237,19 @@ fn main() -> ! {
 
     let mut card = SdCard::init(sdio, &mut delay, 2000).unwrap();
 
-    //card.store_settings(Settings { logger_block: 10 }).unwrap();
-
-    let mut settings = card.load_settings().unwrap();
-    defmt::debug!("Loaded settings: {}", defmt::Debug2Format(&settings));
+    // a card that cannot be read leaves us with the defaults rather than failing to boot
+    let (mut settings, settings_source) = storage::load_settings(&mut card).unwrap_or_else(|e| {
+        error!("Could not read settings: {}", defmt::Debug2Format(&e));
+        (
+            settings::Settings::default(),
+            storage::SettingsSource::Default,
+        )
+    });
+    defmt::debug!(
+        "Loaded settings from {}: {}",
+        settings_source,
+        defmt::Debug2Format(&settings)
+    );
 
     // experiment with RTC
     let mut c = Clock::new(peripherals.RTC);
@@ -216,6 +261,23 @@ fn main() -> ! {
     }
     c.enable_alarm_interrupt(&peripherals.EXTI);
 
+    // the alarms are programmed into the RTC by the `App`, but without stored settings keep the
+    // daily alarm the RTC had from before there were several of them
+    if !matches!(settings_source, storage::SettingsSource::Stored { .. }) {
+        let alarm = c.get_alarm();
+        settings.alarms[0] = Alarm {
+            hour: alarm.hour,
+            minute: alarm.minute,
+            days: Weekdays::EVERY_DAY,
+            enabled: alarm.enabled && alarm.weekday.is_none(),
+            window: 0,
+            ..settings.alarms[0]
+        };
+    }
+    LONG_PRESS_TICKS.store(settings.long_press_ticks(), Ordering::Relaxed);
+    let mut settings_writer =
+        storage::SettingsWriter::new(settings, settings_source, SETTINGS_WRITE_DELAY);
+
     // setup and try MPU6050 I2C3
 
     let i2c3 = I2c::new(
@@ -228,7 +290,7 @@ fn main() -> ! {
         clocks,
     );
 
-    let mut mpu = MPU::new(i2c3, &mut delay, 10).unwrap();
+    let mut mpu = MPU::new(i2c3, &mut delay, settings.mpu_samples as u32).unwrap();
 
     // setup display I2C
     let i2c = I2c::new(
@@ -242,10 +304,15 @@ fn main() -> ! {
     );
 
     let mut display = I2CDisplayDriver::new(i2c, 0x63, 4, 20);
-    display.set_type(4, &mut delay).unwrap();
+    display.set_type(settings.display_type, &mut delay).unwrap();
     display.set_cursor_mode(display::CursorMode::Off).unwrap();
 
-    display.set_backlight_brightness(64).unwrap();
+    display
+        .set_backlight_brightness(settings.backlight_brightness)
+        .unwrap();
+    if let Some(contrast) = settings.contrast {
+        display.set_contrast(contrast).unwrap();
+    }
     display.set_backlight_enabled(true).unwrap();
 
     // write stuff to the screen
@@ -259,27 +326,49 @@ fn main() -> ! {
     defmt::debug!("Calibrated: {}", defmt::Debug2Format(&calib));
     mpu.set_calibration(calib);
 
-    // setup stuff for the menu system
-    let manager: &mut dyn Panel<display::BufferedDisplay<4, 20>> =
-        &mut panel::time::TimePanel::new();
-
-    // setup the shared state
-    let mut panel_state = SharedState {
-        clock: c.get_state(),
-        alarm: c.get_alarm(),
-    };
+    // setup stuff for the menu system, the panels are shown in the order they are registered
+    let mut time_panel = panel::field::FieldPanel::new(panel::time::FIELDS);
+    let mut alarm_panel = panel::field::FieldPanel::new(panel::alarm::FIELDS);
+    let mut stats_panel = panel::stats::StatsPanel::new();
+    let mut sensor_panel = panel::sensor::SensorPanel::new();
+    let mut settings_panel = panel::field::FieldPanel::new(panel::settings::FIELDS);
+    let mut diagnostics_panel = panel::diagnostics::DiagnosticsPanel::new();
+
+    let mut panels: panel::PanelManager<display::BufferedDisplay<4, 20>, 6> =
+        panel::PanelManager::new();
+    panels.register(Panels::Time, &mut time_panel).unwrap();
+    panels.register(Panels::Alarm, &mut alarm_panel).unwrap();
+    panels.register(Panels::Stats, &mut stats_panel).unwrap();
+    panels.register(Panels::Sensor, &mut sensor_panel).unwrap();
+    panels
+        .register(Panels::Settings, &mut settings_panel)
+        .unwrap();
+    panels
+        .register(Panels::Diagnostics, &mut diagnostics_panel)
+        .unwrap();
+
+    let mut app = App::new(&mut panels, &c, settings);
 
     let mut last_cursor_state = CursorState::Off;
-    let mut last_edit_state = false;
-    let mut dialog: Option<dialog::Dialog> = None;
-
-    // create logger with one minute timeout
-    let mut logger = Logger::new();
+    let mut backlight = settings.backlight_brightness;
+
+    // open the log on the card, leaving any log in a format we cannot append to untouched
+    app.state_mut().diagnostics.settings_source = settings_writer.source();
+    let (mut logger, opened) = match Logger::open(&mut card) {
+        Ok(logger) => {
+            debug!("Appending to the log at block {}", logger.next_block());
+            (Some(logger), Ok(()))
+        }
+        Err(e) => (None, Err(e)),
+    };
+    check_log(&mut logger, opened, &mut app.state_mut().diagnostics);
 
-    // enable TIM5 interrupt in the NVIC before starting the loop
+    // enable TIM5 and TIM2 interrupts in the NVIC before starting the loop
     stm32::NVIC::unpend(stm32f4xx_hal::interrupt::TIM5);
+    stm32::NVIC::unpend(stm32f4xx_hal::interrupt::TIM2);
     unsafe {
         stm32::NVIC::unmask(stm32f4xx_hal::interrupt::TIM5);
+        stm32::NVIC::unmask(stm32f4xx_hal::interrupt::TIM2);
     };
 
     loop {
@@ -293,92 +382,97 @@ fn main() -> ! {
         while let Some(evt) = free(|cs| EVENT_QUEUE.take(cs)) {
             use InterruptEvent::*;
 
-            if let Some(d) = dialog {
-                match evt {
-                    LongPress | ShortPress => {
-                        dialog = None;
-                        // post the event that is supposed to happen after the dialog
-                        if let Some(e) = d.event {
-                            free(|cs| EVENT_QUEUE.put(cs, e));
-                        }
-                    }
-                    _ => (),
-                }
-            } else {
-                match evt {
-                    Tick => {
-                        led.toggle().unwrap();
-
-                        // fetch the current date and time from the clock if the panel is not editing
-                        if last_edit_state && !manager.is_editing() {
-                            c.set_state(panel_state.clock);
-                            c.set_alarm(panel_state.alarm);
-                        }
+            match evt {
+                Tick => {
+                    led.toggle().unwrap();
 
-                        if !manager.is_editing() {
-                            panel_state.clock = c.get_state();
-                            panel_state.alarm = c.get_alarm();
-                        }
+                    if let Some(m) = mpu.tick() {
+                        defmt::debug!("measurement: {:?}", defmt::Debug2Format(&m));
+                        // may go off early for an alarm with a wake window, logged below
+                        app.record_measurement(&m, &c);
 
-                        last_edit_state = manager.is_editing();
-
-                        if let Some(m) = mpu.tick() {
-                            defmt::debug!("measurement: {:?}", defmt::Debug2Format(&m));
-                            logger
-                                .append(
-                                    &c.get_state(),
-                                    LogContents::Measurement(m),
-                                    &mut card,
-                                    &mut settings,
-                                )
-                                .expect("Error appending to log");
+                        if let Some(l) = &mut logger {
+                            let result =
+                                l.append(&c.get_state(), LogContents::Measurement(m), &mut card);
+                            check_log(&mut logger, result, &mut app.state_mut().diagnostics);
                         }
                     }
-                    Encoder(change) => {
-                        let mut c = change;
-                        while c > 0 {
-                            manager.next(&mut panel_state);
-                            c -= 1;
-                        }
 
-                        while c < 0 {
-                            manager.previous(&mut panel_state);
-                            c += 1;
+                    // write back the settings once they have been left alone for a while
+                    settings_writer.update(app.state().settings);
+                    match settings_writer.tick(&mut card) {
+                        Ok(true) => {
+                            debug!("Stored settings in {}", settings_writer.source());
+                            app.state_mut().diagnostics.settings_source = settings_writer.source();
+                        }
+                        Ok(false) => (),
+                        Err(e) => {
+                            error!("Could not store settings: {}", defmt::Debug2Format(&e))
                         }
-
-                        // logf!("Encoder: {:?}\n", change);
-                        defmt::info!("Encoder: {=i8}", change);
                     }
-                    LongPress => {
-                        manager.leave(&mut panel_state);
-                        logger
-                            .flush(&mut card, &mut settings)
-                            .expect("Error flushing log");
+                }
+                Encoder(change) => defmt::info!("Encoder: {=i8}", change),
+                LongPress => {
+                    if let Some(l) = &mut logger {
+                        let result = l.flush(&mut card);
+                        check_log(&mut logger, result, &mut app.state_mut().diagnostics);
                     }
-                    ShortPress => manager.enter(&mut panel_state),
-                    Alarm => {
-                        defmt::info!("Alarm Interrupt! {}", defmt::Debug2Format(&c.get_alarm()));
-
-                        // free(|cs| {
-                        //     let diag = crate::Dialog::new("Alarm triggered", None);
-                        //     EVENT_QUEUE.put(cs, InterruptEvent::Dialog(diag));
-                        // });
-                        c.alarm_reset();
-                        dialog = Some(crate::Dialog::new("Alarm triggered", None));
-                    } // Dialog(d) => dialog = Some(d),
+                }
+                Alarm => {
+                    defmt::info!("Alarm Interrupt! {}", defmt::Debug2Format(&c.get_alarm()))
+                }
+                ShortPress => (),
+            }
+
+            app.handle_event(evt, &mut c);
+
+            if let Some(e) = app.take_ring_event() {
+                info!("Alarm: {}", e);
+                if let Some(l) = &mut logger {
+                    let result = l.append(&c.get_state(), LogContents::Ring(e), &mut card);
+                    check_log(&mut logger, result, &mut app.state_mut().diagnostics);
                 }
             }
         }
 
-        disp.clear().unwrap();
+        // play the alarm tone while ringing, the TIM2 interrupt keeps it going
+        let ringing = app.ringer().is_ringing();
+        let tone = app.alarm_tone();
+        let volume = app.alarm_volume();
+        free(|cs| {
+            PLAYER.try_borrow_mut(cs, |player| {
+                // getting louder over the first minute of ringing
+                player.set_volume(volume);
+                if ringing && !player.is_playing() {
+                    player.start(&tone::melody(tone), true);
+                } else if !ringing && player.is_playing() {
+                    player.stop();
+                }
+                Some(())
+            })
+        });
 
-        let cursor_state = if let Some(d) = dialog {
-            d.display(&mut disp).unwrap();
-            CursorState::Off
-        } else {
-            manager.display(&mut disp, &mut panel_state).unwrap();
-            manager.get_cursor_state(&panel_state)
-        };
+        // follow the day and night brightness, and the sunrise before an alarm
+        let brightness = app.backlight();
+        if brightness != backlight {
+            display.set_backlight_brightness(brightness).unwrap();
+            backlight = brightness;
+        }
+
+        // apply the settings right away while they are being edited
+        let edited = app.state().settings;
+        if edited != settings {
+            if edited.contrast != settings.contrast {
+                if let Some(contrast) = edited.contrast {
+                    display.set_contrast(contrast).unwrap();
+                }
+            }
+            mpu.set_samples(edited.mpu_samples as u32);
+            LONG_PRESS_TICKS.store(edited.long_press_ticks(), Ordering::Relaxed);
+            settings = edited;
+        }
+
+        let cursor_state = app.render(&mut disp).unwrap();
 
         // update the display after processing all events
         let changed = disp.apply(&mut display).unwrap();
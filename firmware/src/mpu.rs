@@ -4,16 +4,10 @@ use embedded_hal::{
     blocking::i2c::{Write, WriteRead},
 };
 use mpu6050::Mpu6050;
-use serde::{Deserialize, Serialize};
 
-use crate::vec::Vec3f;
+use dsaclk_core::vec::Vec3f;
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
-pub struct Measurement {
-    acc_mean: Vec3f,
-    temp_mean: f32,
-    gyro_mag_max: f32,
-}
+pub use dsaclk_core::mpu::Measurement;
 
 #[derive(Default, Debug)]
 pub struct CalibrationOffset {
@@ -82,6 +76,11 @@ where
         }
     }
 
+    /// Sets the number of samples averaged into each `Measurement`, from the next one on.
+    pub fn set_samples(&mut self, samples: u32) {
+        self.samples = samples;
+    }
+
     pub fn set_calibration(&mut self, c: CalibrationOffset) {
         self.calib = c
     }
@@ -1,4 +1,3 @@
-use cortex_m::{delay::Delay, prelude::_embedded_hal_blocking_delay_DelayMs};
 use stm32f4xx_hal::{
     bb,
     gpio::{gpiob::PB7, Alternate, AF2},
@@ -7,15 +6,69 @@ use stm32f4xx_hal::{
     stm32 as stm32f401,
 };
 
+use dsaclk_core::{
+    alarm::MAX_VOLUME,
+    tone::{Note, Sequencer, ToneOutput},
+};
+
+/// Plays melodies on the buzzer, advanced by `tick` without blocking.
 pub struct Player {
+    buzzer: Buzzer,
+    sequencer: Sequencer,
+}
+
+impl Player {
+    pub fn new(timer: stm32f401::TIM4, pin: PB7<Alternate<AF2>>, clocks: &Clocks) -> Player {
+        Player {
+            buzzer: Buzzer::new(timer, pin, clocks),
+            sequencer: Sequencer::new(),
+        }
+    }
+
+    /// Starts playing the notes, over and over again if `repeat`.
+    pub fn start(&mut self, notes: &[Note], repeat: bool) {
+        self.sequencer.start(notes, repeat, &mut self.buzzer);
+    }
+
+    pub fn stop(&mut self) {
+        self.sequencer.stop(&mut self.buzzer);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.sequencer.is_playing()
+    }
+
+    /// Sets the volume in percent, up to `MAX_VOLUME`, taking effect right away.
+    pub fn set_volume(&mut self, volume: u8) {
+        self.buzzer.set_volume(volume);
+    }
+
+    /// Advances the playing melody by `elapsed` milliseconds.
+    pub fn tick(&mut self, elapsed: u32) {
+        self.sequencer.tick(elapsed, &mut self.buzzer);
+    }
+
+    #[allow(dead_code)]
+    pub fn release(self) -> (TIM4, PB7<Alternate<AF2>>) {
+        self.buzzer.release()
+    }
+}
+
+/// The buzzer on PB7, driven by TIM4 in PWM mode at the frequency of the tone. The volume sets
+/// the duty cycle, with `MAX_VOLUME` at 50% where the buzzer is the loudest and 0 keeping it
+/// silent.
+struct Buzzer {
     timer: stm32f401::TIM4,
     _pin: PB7<Alternate<AF2>>,
     psc_freq: u32,
+    /// Timer ticks per period of the tone.
+    period: u32,
+    volume: u8,
+    playing: bool,
 }
 
-#[allow(dead_code)]
-impl Player {
-    pub fn new(timer: stm32f401::TIM4, pin: PB7<Alternate<AF2>>, clocks: &Clocks) -> Player {
+impl Buzzer {
+    fn new(timer: stm32f401::TIM4, pin: PB7<Alternate<AF2>>, clocks: &Clocks) -> Buzzer {
         // NOTE: this is taken from the stm32f4xx-hal crate and used to enable and reset TIM4
         unsafe {
             // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
@@ -32,17 +85,17 @@ impl Player {
         timer.smcr.modify(
             |_, w| w.ece().disabled().sms().disabled(), // clock directly from the internal clock
         );
-        // write prescaler to generate 10kHz clock
-        let psc_freq = 10000;
+        // write prescaler to generate 1MHz clock, fine enough for the duty cycle of high tones
+        let psc_freq = 1_000_000;
         timer
             .psc
             .write(|w| w.psc().bits((clocks.pclk2().0 / psc_freq) as u16 - 1));
 
-        // 3a) setup CH2 as toggling output (= PB7 AF02)  and
+        // 3a) setup CH2 as output (= PB7 AF02), kept low until a tone is played, and
         // 3c) disable preload feature
         timer
             .ccmr1_output_mut()
-            .modify(|_, w| w.oc2m().toggle().oc2pe().disabled());
+            .modify(|_, w| w.oc2m().force_inactive().oc2pe().disabled());
 
         // 3b) select output polarity
         timer.ccer.modify(|_, w| w.cc2p().clear_bit());
@@ -50,53 +103,77 @@ impl Player {
         // 3d) enable capture/compare 2 output
         timer.ccer.modify(|_, w| w.cc2e().set_bit());
 
-        Player {
+        Buzzer {
             timer,
             _pin: pin,
             psc_freq,
+            period: 1,
+            volume: MAX_VOLUME,
+            playing: false,
         }
     }
 
-    pub fn play(&self, delay: &mut Delay) {
-        // write ARR to decide the output frequency
-        self.set_frequency(440);
-        self.start_tone();
-        delay.delay_ms(1000);
-
-        self.set_frequency(1000);
-
-        delay.delay_ms(100);
-
-        self.set_frequency(2000);
-
-        delay.delay_ms(1000);
-        self.stop_tone();
-    }
-
-    pub fn set_frequency(&self, freq: u32) {
+    fn set_frequency(&mut self, freq: u32) {
+        // the lowest tones are limited by the 16 bit counter
+        self.period = (self.psc_freq / freq.max(1)).clamp(2, 0x1_0000);
         self.timer
             .arr
-            .write(|w| w.arr().bits((self.psc_freq / freq) as u16));
+            .write(|w| w.arr().bits((self.period - 1) as u16));
+        self.set_duty();
     }
 
-    pub fn start_tone(&self) {
-        // set up toggle function and enable the timer
+    fn set_volume(&mut self, volume: u8) {
+        self.volume = volume.min(MAX_VOLUME);
+        self.set_duty();
+    }
+
+    fn set_duty(&self) {
+        // counting down, the output is active while the counter is at most CCR2, ie for CCR2 + 1
+        // ticks of every period, so it has to be forced low for no ticks at all
+        let active = self.period * self.volume as u32 / (2 * MAX_VOLUME as u32);
+        if !self.playing || active == 0 {
+            self.timer
+                .ccmr1_output_mut()
+                .modify(|_, w| w.oc2m().force_inactive());
+            return;
+        }
+
+        let ccr = (active - 1).min(self.period - 1);
+        // NOTE(unsafe) any value is a valid compare value
+        self.timer.ccr2.write(|w| unsafe { w.bits(ccr) });
         self.timer
             .ccmr1_output_mut()
-            .modify(|_, w| w.oc2m().toggle());
+            .modify(|_, w| w.oc2m().pwm_mode1());
+    }
+
+    fn start(&mut self) {
+        // set up PWM function and enable the timer
+        self.playing = true;
+        self.set_duty();
         self.timer.cr1.modify(|_, w| w.cen().enabled().dir().down());
     }
 
-    pub fn stop_tone(&self) {
+    fn stop(&mut self) {
         // disable the timer
         self.timer.cr1.modify(|_, w| w.cen().disabled());
-        //force output low
-        self.timer
-            .ccmr1_output_mut()
-            .modify(|_, w| w.oc2m().force_inactive());
+        // force output low
+        self.playing = false;
+        self.set_duty();
     }
 
-    pub fn release(self) -> (TIM4, PB7<Alternate<AF2>>) {
+    fn release(self) -> (TIM4, PB7<Alternate<AF2>>) {
         (self.timer, self._pin)
     }
 }
+
+impl ToneOutput for Buzzer {
+    fn start_tone(&mut self, freq: u16) {
+        // write ARR to decide the output frequency, and CCR2 to keep the duty cycle
+        self.set_frequency(freq as u32);
+        self.start();
+    }
+
+    fn stop_tone(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,331 @@
+//! Sleep/wake scoring of the logged MPU measurements.
+//!
+//! The measurements are resampled into fixed-length epochs, each with an activity count computed
+//! from the movement of the sensor, and the epochs are scored with the Cole-Kripke or Sadeh
+//! algorithm. Both algorithms were designed for the counts of wrist-worn actigraphs, so the
+//! `ActivityConfig` scaling may need calibration for a sensor lying in the bed.
+use std::convert::TryFrom;
+
+use dsaclk_core::{
+    logger::{LogContents, LogEntry},
+    time::Timestamp,
+    vec::Vec3f,
+};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum EpochLength {
+    Seconds30,
+    Seconds60,
+}
+
+impl EpochLength {
+    pub fn seconds(self) -> i64 {
+        match self {
+            EpochLength::Seconds30 => 30,
+            EpochLength::Seconds60 => 60,
+        }
+    }
+}
+
+/// How the activity count is computed from each `Measurement`.
+#[derive(Debug, Clone, Copy)]
+pub struct ActivityConfig {
+    /// Gyroscope magnitude (rad/s) below which the sensor is considered to be still.
+    pub gyro_threshold: f32,
+    /// Counts per rad/s of gyroscope magnitude above the threshold.
+    pub gyro_scale: f32,
+    /// Counts per g of change in mean acceleration between consecutive measurements.
+    pub acc_scale: f32,
+}
+
+impl Default for ActivityConfig {
+    fn default() -> Self {
+        ActivityConfig {
+            gyro_threshold: 0.05,
+            gyro_scale: 100.0,
+            acc_scale: 200.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Epoch {
+    /// Start of the epoch in seconds since 2000-01-01 00:00:00.
+    pub start: i64,
+    pub activity: f32,
+    /// Number of measurements in the epoch, zero for gaps in the log.
+    pub samples: usize,
+    pub temp_mean: Option<f32>,
+}
+
+/// Resamples the measurements into consecutive epochs, aligned to whole multiples of the epoch
+/// length. Epochs without any measurements are included with zero activity and samples.
+pub fn epochs(entries: &[LogEntry], length: EpochLength, config: &ActivityConfig) -> Vec<Epoch> {
+    let len = length.seconds();
+    let mut epochs: Vec<Epoch> = Vec::new();
+    let mut last_acc: Option<Vec3f> = None;
+
+    for entry in entries {
+        let m = match &entry.contents {
+            LogContents::Measurement(m) => m,
+            _ => continue,
+        };
+
+        let start = Timestamp::from(&entry.timestamp).seconds().div_euclid(len) * len;
+
+        // the log should be in order, but the clock may have been set backwards
+        if epochs.last().is_some_and(|e| start < e.start) {
+            continue;
+        }
+
+        // open a new epoch, filling any gap since the last one
+        while epochs.last().is_none_or(|e| e.start < start) {
+            let next = epochs.last().map_or(start, |e| e.start + len);
+            if next < start {
+                // do not compare against movement from before the gap
+                last_acc = None;
+            }
+            epochs.push(Epoch {
+                start: next,
+                activity: 0.0,
+                samples: 0,
+                temp_mean: None,
+            });
+        }
+
+        let mut activity = (m.gyro_mag_max - config.gyro_threshold).max(0.0) * config.gyro_scale;
+        if let Some(last) = last_acc {
+            activity += (m.acc_mean - last).len2().sqrt() * config.acc_scale;
+        }
+        last_acc = Some(m.acc_mean);
+
+        let epoch = epochs.last_mut().unwrap();
+        epoch.temp_mean = Some(
+            (epoch.temp_mean.unwrap_or(0.0) * epoch.samples as f32 + m.temp_mean)
+                / (epoch.samples + 1) as f32,
+        );
+        epoch.activity += activity;
+        epoch.samples += 1;
+    }
+
+    epochs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum SleepWake {
+    Sleep,
+    Wake,
+}
+
+/// The Cole-Kripke scaling factor and weights for the epochs A-4 to A+2.
+pub struct ColeKripkeParams {
+    pub scale: f32,
+    pub weights: [f32; 7],
+}
+
+/// Parameters published by Cole et al. (1992) for 1-minute epochs.
+pub const COLE_KRIPKE_60S: ColeKripkeParams = ColeKripkeParams {
+    scale: 0.001,
+    weights: [106.0, 54.0, 58.0, 76.0, 230.0, 74.0, 67.0],
+};
+
+/// Parameters published by Cole et al. (1992) for 30-second epochs.
+pub const COLE_KRIPKE_30S: ColeKripkeParams = ColeKripkeParams {
+    scale: 0.0001,
+    weights: [50.0, 30.0, 14.0, 28.0, 121.0, 8.0, 50.0],
+};
+
+/// Scores each epoch with the Cole-Kripke algorithm: sleep if
+/// `D = P(W-4 A-4 + ... + W0 A0 + ... + W+2 A+2) < 1`.
+/// Epochs outside the recording count as zero activity.
+pub fn cole_kripke(epochs: &[Epoch], params: &ColeKripkeParams) -> Vec<SleepWake> {
+    (0..epochs.len())
+        .map(|i| {
+            let d: f32 = params
+                .weights
+                .iter()
+                .zip(-4..=2)
+                .filter_map(|(w, offset)| {
+                    let j = i as i64 + offset;
+                    epochs.get(usize::try_from(j).ok()?).map(|e| w * e.activity)
+                })
+                .sum();
+
+            if params.scale * d < 1.0 {
+                SleepWake::Sleep
+            } else {
+                SleepWake::Wake
+            }
+        })
+        .collect()
+}
+
+/// Scores 1-minute epochs with the Sadeh et al. (1994) algorithm: sleep if
+/// `PS = 7.601 - 0.065 MW5 - 1.08 NAT - 0.056 SD6 - 0.703 LG >= 0`, where
+///
+/// * `MW5` is the mean activity in the 11 epochs centered on the scored one,
+/// * `NAT` the number of those epochs with an activity of at least 50 and less than 100,
+/// * `SD6` the standard deviation of the activity of the scored and the 5 preceding epochs,
+/// * `LG` the natural logarithm of the activity of the scored epoch plus one.
+///
+/// Activity is capped at 300 counts, and the windows are truncated at the ends of the recording.
+pub fn sadeh(epochs: &[Epoch]) -> Vec<SleepWake> {
+    let activity: Vec<f32> = epochs.iter().map(|e| e.activity.min(300.0)).collect();
+
+    (0..activity.len())
+        .map(|i| {
+            let window = &activity[i.saturating_sub(5)..(i + 6).min(activity.len())];
+            let mw5 = window.iter().sum::<f32>() / window.len() as f32;
+            let nat = window
+                .iter()
+                .filter(|&&a| (50.0..100.0).contains(&a))
+                .count() as f32;
+
+            let previous = &activity[i.saturating_sub(5)..=i];
+            let sd6 = std_dev(previous);
+
+            let lg = (activity[i] + 1.0).ln();
+
+            let ps = 7.601 - 0.065 * mw5 - 1.08 * nat - 0.056 * sd6 - 0.703 * lg;
+            if ps >= 0.0 {
+                SleepWake::Sleep
+            } else {
+                SleepWake::Wake
+            }
+        })
+        .collect()
+}
+
+/// Sample standard deviation, zero for fewer than two values.
+fn std_dev(values: &[f32]) -> f32 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / (values.len() - 1) as f32;
+    var.sqrt()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    ColeKripke(EpochLength),
+    /// Sadeh is only defined for 1-minute epochs.
+    Sadeh,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoredEpoch {
+    #[serde(flatten)]
+    pub epoch: Epoch,
+    pub state: SleepWake,
+}
+
+/// Resamples and scores the decoded log entries.
+pub fn score(
+    entries: &[LogEntry],
+    algorithm: Algorithm,
+    config: &ActivityConfig,
+) -> Vec<ScoredEpoch> {
+    let (epochs, states) = match algorithm {
+        Algorithm::ColeKripke(length) => {
+            let epochs = epochs(entries, length, config);
+            let params = match length {
+                EpochLength::Seconds30 => &COLE_KRIPKE_30S,
+                EpochLength::Seconds60 => &COLE_KRIPKE_60S,
+            };
+            let states = cole_kripke(&epochs, params);
+            (epochs, states)
+        }
+        Algorithm::Sadeh => {
+            let epochs = epochs(entries, EpochLength::Seconds60, config);
+            let states = sadeh(&epochs);
+            (epochs, states)
+        }
+    };
+
+    epochs
+        .into_iter()
+        .zip(states)
+        .map(|(epoch, state)| ScoredEpoch { epoch, state })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dsaclk_core::{logger::LogTimestamp, mpu::Measurement};
+
+    /// One measurement per second from 00:00:00, moving during the given minutes.
+    fn recording(minutes: u32, moving: impl Fn(u32) -> bool) -> Vec<LogEntry> {
+        (0..minutes * 60)
+            .map(|s| LogEntry {
+                timestamp: LogTimestamp {
+                    hour: (s / 3600) as u8,
+                    minute: (s / 60 % 60) as u8,
+                    second: (s % 60) as u8,
+                    day: 1,
+                    month: 1,
+                    year: 21,
+                },
+                contents: LogContents::Measurement(Measurement {
+                    acc_mean: Vec3f(0.0, 0.0, 1.0),
+                    temp_mean: 20.0,
+                    gyro_mag_max: if moving(s / 60) { 1.0 } else { 0.01 },
+                }),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resampling_fills_gaps() {
+        let mut entries = recording(2, |_| false);
+        entries.drain(10..100);
+
+        let e = epochs(&entries, EpochLength::Seconds30, &ActivityConfig::default());
+        assert_eq!(
+            e.iter().map(|e| e.samples).collect::<Vec<_>>(),
+            [10, 0, 0, 20]
+        );
+        assert_eq!(e[1].start - e[0].start, 30);
+        assert_eq!(e[0].temp_mean, Some(20.0));
+        assert_eq!(e[1].temp_mean, None);
+    }
+
+    #[test]
+    fn activity_counts_movement() {
+        let entries = recording(2, |m| m == 1);
+        let e = epochs(&entries, EpochLength::Seconds60, &ActivityConfig::default());
+        assert_eq!(e[0].activity, 0.0);
+        assert!((e[1].activity - 60.0 * 95.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn cole_kripke_scores_movement_as_wake() {
+        let entries = recording(30, |m| (10..15).contains(&m));
+
+        for length in [EpochLength::Seconds30, EpochLength::Seconds60] {
+            let scored = score(
+                &entries,
+                Algorithm::ColeKripke(length),
+                &ActivityConfig::default(),
+            );
+            let per_minute = (60 / length.seconds()) as usize;
+
+            assert_eq!(scored[5 * per_minute].state, SleepWake::Sleep);
+            assert_eq!(scored[12 * per_minute].state, SleepWake::Wake);
+            assert_eq!(scored[25 * per_minute].state, SleepWake::Sleep);
+        }
+    }
+
+    #[test]
+    fn sadeh_scores_movement_as_wake() {
+        let entries = recording(40, |m| (10..15).contains(&m));
+        let scored = score(&entries, Algorithm::Sadeh, &ActivityConfig::default());
+
+        assert_eq!(scored.len(), 40);
+        assert_eq!(scored[3].state, SleepWake::Sleep);
+        assert_eq!(scored[12].state, SleepWake::Wake);
+        assert_eq!(scored[35].state, SleepWake::Sleep);
+    }
+}
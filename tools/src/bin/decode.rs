@@ -0,0 +1,123 @@
+//! Decodes the log written by the firmware from a raw SD-card image into CSV or JSON Lines.
+//!
+//! ```text
+//! dsaclk-decode <image> [--format csv|jsonl] [--end <block>] [--output <file>]
+//! ```
+use std::{
+    env,
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    process,
+};
+
+use dsaclk_core::storage::FileBlockDevice;
+use dsaclk_tools::decode;
+
+enum Format {
+    Csv,
+    JsonLines,
+}
+
+struct Args {
+    image: String,
+    format: Format,
+    end: Option<u32>,
+    output: Option<String>,
+}
+
+const USAGE: &str =
+    "usage: dsaclk-decode <image> [--format csv|jsonl] [--end <block>] [--output <file>]";
+
+fn parse_args() -> Result<Args, String> {
+    let mut image = None;
+    let mut format = Format::Csv;
+    let mut end = None;
+    let mut output = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "-f" | "--format" => {
+                format = match value(&arg)?.as_str() {
+                    "csv" => Format::Csv,
+                    "jsonl" => Format::JsonLines,
+                    f => return Err(format!("unknown format '{}'", f)),
+                }
+            }
+            "-e" | "--end" => {
+                end = Some(
+                    value(&arg)?
+                        .parse()
+                        .map_err(|e| format!("invalid block: {}", e))?,
+                )
+            }
+            "-o" | "--output" => output = Some(value(&arg)?),
+            "-h" | "--help" => return Err(USAGE.into()),
+            _ if image.is_none() => image = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(Args {
+        image: image.ok_or(USAGE)?,
+        format,
+        end,
+        output,
+    })
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let mut card = FileBlockDevice::new(File::open(&args.image)?)?;
+
+    // the settings block tells us where the logger will write next, unless overridden
+    let log = decode::read_entries(&mut card, args.end)?;
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+
+    match args.format {
+        Format::Csv => {
+            writeln!(out, "{}", decode::CSV_HEADER)?;
+            for entry in &log.entries {
+                writeln!(out, "{}", decode::csv_row(entry))?;
+            }
+        }
+        Format::JsonLines => {
+            for entry in &log.entries {
+                writeln!(out, "{}", serde_json::to_string(entry)?)?;
+            }
+        }
+    }
+    out.flush()?;
+
+    eprintln!("Decoded {} entries", log.entries.len());
+    if log.is_damaged() {
+        eprintln!(
+            "Skipped {} damaged block(s) {:?} and {} damaged frame(s)",
+            log.damaged_blocks.len(),
+            log.damaged_blocks,
+            log.damaged_frames
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,109 @@
+//! Prints the sleep metrics of each night recorded in a raw SD-card image.
+//!
+//! ```text
+//! dsaclk-report <image> [--algorithm ck30|ck60|sadeh] [--json] [--end <block>]
+//! ```
+use std::{env, error::Error, fs::File, process};
+
+use dsaclk_core::storage::FileBlockDevice;
+use dsaclk_tools::{
+    actigraphy::{self, ActivityConfig, Algorithm, EpochLength},
+    decode,
+    report::{self, NightConfig},
+};
+
+struct Args {
+    image: String,
+    algorithm: Algorithm,
+    json: bool,
+    end: Option<u32>,
+}
+
+const USAGE: &str =
+    "usage: dsaclk-report <image> [--algorithm ck30|ck60|sadeh] [--json] [--end <block>]";
+
+fn parse_args() -> Result<Args, String> {
+    let mut image = None;
+    let mut algorithm = Algorithm::ColeKripke(EpochLength::Seconds60);
+    let mut json = false;
+    let mut end = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "-a" | "--algorithm" => {
+                algorithm = match value(&arg)?.as_str() {
+                    "ck30" => Algorithm::ColeKripke(EpochLength::Seconds30),
+                    "ck60" => Algorithm::ColeKripke(EpochLength::Seconds60),
+                    "sadeh" => Algorithm::Sadeh,
+                    a => return Err(format!("unknown algorithm '{}'", a)),
+                }
+            }
+            "--json" => json = true,
+            "-e" | "--end" => {
+                end = Some(
+                    value(&arg)?
+                        .parse()
+                        .map_err(|e| format!("invalid block: {}", e))?,
+                )
+            }
+            "-h" | "--help" => return Err(USAGE.into()),
+            _ if image.is_none() => image = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(Args {
+        image: image.ok_or(USAGE)?,
+        algorithm,
+        json,
+        end,
+    })
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let mut card = FileBlockDevice::new(File::open(&args.image)?)?;
+    let log = decode::read_entries(&mut card, args.end)?;
+    if log.is_damaged() {
+        eprintln!(
+            "Skipped {} damaged block(s) {:?} and {} damaged frame(s)",
+            log.damaged_blocks.len(),
+            log.damaged_blocks,
+            log.damaged_frames
+        );
+    }
+
+    let epochs = actigraphy::score(&log.entries, args.algorithm, &ActivityConfig::default());
+    let nights = report::nightly_reports(&epochs, &NightConfig::default());
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&nights)?);
+    } else {
+        for night in &nights {
+            println!("{}", night);
+        }
+        eprintln!(
+            "Found {} night(s) in {} entries",
+            nights.len(),
+            log.entries.len()
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
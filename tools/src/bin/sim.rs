@@ -0,0 +1,271 @@
+//! Runs the clock user interface in the terminal.
+//!
+//! The 4x20 display is drawn as a box, with the terminal cursor showing the display cursor.
+//! Arrow keys turn the encoder and Enter is the push button. Holding Enter for the long press
+//! duration of the settings gives a long press in terminals that report key releases, in other
+//! terminals `l` or Backspace can be used instead. `a` triggers the alarm and `q` quits.
+//! Turning past the first or last field of a panel switches to the previous or next panel.
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crossterm::{
+    cursor::{self, SetCursorStyle},
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::Print,
+    terminal::{self, ClearType},
+};
+use dsaclk_core::{
+    alarm::RingEvent,
+    app::{App, POLL_FREQ},
+    button::{Button, ButtonState},
+    clock::{AlarmState, ClockState, Rtc},
+    display::BufferedDisplay,
+    event::InterruptEvent,
+    panel::{
+        self, diagnostics::DiagnosticsPanel, field::FieldPanel, sensor::SensorPanel,
+        stats::StatsPanel, CursorState, PanelManager, Panels,
+    },
+    settings::Settings,
+    time::{self, Timestamp},
+};
+
+type Screen = BufferedDisplay<4, 20>;
+
+/// Simulates the RTC, advancing the clock from the host time.
+struct SimRtc {
+    state: ClockState,
+    alarm: AlarmState,
+    alarm_flag: bool,
+    last_second: Instant,
+}
+
+impl SimRtc {
+    fn new(state: ClockState) -> Self {
+        SimRtc {
+            state,
+            alarm: AlarmState::default(),
+            alarm_flag: false,
+            last_second: Instant::now(),
+        }
+    }
+
+    /// Advances the clock by the elapsed whole seconds. Returns `true` if the alarm triggered.
+    fn update(&mut self) -> bool {
+        let mut triggered = false;
+        while self.last_second.elapsed() >= Duration::from_secs(1) {
+            self.last_second += Duration::from_secs(1);
+            self.state = (Timestamp::from(self.state) + time::Duration::from_seconds(1)).into();
+
+            // like alarm A of the RTC, match on the weekday, hour and minute at the start of the
+            // minute
+            let s = &self.state;
+            if self.alarm.enabled
+                && !self.alarm_flag
+                && self.alarm.weekday.is_none_or(|w| w == s.weekday)
+                && (s.hour, s.minute, s.second) == (self.alarm.hour, self.alarm.minute, 0)
+            {
+                self.alarm_flag = true;
+                triggered = true;
+            }
+        }
+        triggered
+    }
+}
+
+impl Rtc for SimRtc {
+    fn get_state(&self) -> ClockState {
+        self.state
+    }
+
+    fn set_state(&mut self, state: ClockState) {
+        self.state = state;
+        self.last_second = Instant::now();
+    }
+
+    fn get_alarm(&self) -> AlarmState {
+        self.alarm
+    }
+
+    fn set_alarm(&mut self, alarm: AlarmState) {
+        self.alarm = alarm;
+    }
+
+    fn alarm_reset(&mut self) {
+        self.alarm_flag = false;
+    }
+}
+
+/// The current UTC time of the host.
+fn host_time() -> ClockState {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    // seconds from 1970-01-01 to 2000-01-01
+    Timestamp::from_seconds(secs as i64 - 946_684_800).into()
+}
+
+fn draw(
+    out: &mut impl Write,
+    screen: &Screen,
+    cursor: &CursorState,
+    ring: Option<RingEvent>,
+    backlight: u8,
+) -> io::Result<()> {
+    queue!(out, cursor::MoveTo(0, 0))?;
+    queue!(out, Print(format!("┌{}┐\r\n", "─".repeat(20))))?;
+    for row in screen.rows() {
+        let text: String = row
+            .iter()
+            .map(|&c| if c.is_ascii_graphic() { c as char } else { ' ' })
+            .collect();
+        queue!(out, Print(format!("│{}│\r\n", text)))?;
+    }
+    queue!(out, Print(format!("└{}┘\r\n", "─".repeat(20))))?;
+
+    queue!(
+        out,
+        terminal::Clear(ClearType::CurrentLine),
+        Print(format!(
+            "cursor: {:?}, backlight: {}, last ring event: {:?}\r\n",
+            cursor, backlight, ring
+        )),
+        Print("arrows: turn, enter: press (l: long press), a: alarm, q: quit")
+    )?;
+
+    match *cursor {
+        CursorState::Off => queue!(out, cursor::Hide)?,
+        CursorState::Underline(r, c) => queue!(
+            out,
+            cursor::MoveTo(c as u16 + 1, r as u16 + 1),
+            SetCursorStyle::SteadyUnderScore,
+            cursor::Show
+        )?,
+        CursorState::Blinking(r, c) => queue!(
+            out,
+            cursor::MoveTo(c as u16 + 1, r as u16 + 1),
+            SetCursorStyle::BlinkingBlock,
+            cursor::Show
+        )?,
+    }
+    out.flush()
+}
+
+fn run(out: &mut impl Write, key_releases: bool) -> io::Result<()> {
+    let mut rtc = SimRtc::new(host_time());
+    let mut time_panel = FieldPanel::new(panel::time::FIELDS);
+    let mut alarm_panel = FieldPanel::new(panel::alarm::FIELDS);
+    let mut stats_panel = StatsPanel::new();
+    let mut sensor_panel = SensorPanel::new();
+    let mut settings_panel = FieldPanel::new(panel::settings::FIELDS);
+    let mut diagnostics_panel = DiagnosticsPanel::new();
+
+    // same panels as the firmware
+    let mut panels: PanelManager<Screen, 6> = PanelManager::new();
+    panels.register(Panels::Time, &mut time_panel).unwrap();
+    panels.register(Panels::Alarm, &mut alarm_panel).unwrap();
+    panels.register(Panels::Stats, &mut stats_panel).unwrap();
+    panels.register(Panels::Sensor, &mut sensor_panel).unwrap();
+    panels
+        .register(Panels::Settings, &mut settings_panel)
+        .unwrap();
+    panels
+        .register(Panels::Diagnostics, &mut diagnostics_panel)
+        .unwrap();
+
+    let mut app: App<Screen> = App::new(&mut panels, &rtc, Settings::default());
+    let mut screen = Screen::new();
+
+    let mut events = VecDeque::new();
+    let mut button = ButtonState::new();
+    let mut button_pressed = false;
+    let mut ring = None;
+
+    let tick = Duration::from_secs(1) / POLL_FREQ;
+    let mut next_tick = Instant::now();
+
+    loop {
+        let timeout = next_tick.saturating_duration_since(Instant::now());
+        if event::poll(timeout)? {
+            if let Event::Key(KeyEvent { code, kind, .. }) = event::read()? {
+                let pressed = kind != KeyEventKind::Release;
+                match code {
+                    KeyCode::Enter if key_releases => button_pressed = pressed,
+                    _ if !pressed => (),
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Right | KeyCode::Down => events.push_back(InterruptEvent::Encoder(1)),
+                    KeyCode::Left | KeyCode::Up => events.push_back(InterruptEvent::Encoder(-1)),
+                    KeyCode::Enter => events.push_back(InterruptEvent::ShortPress),
+                    KeyCode::Char('l') | KeyCode::Backspace => {
+                        events.push_back(InterruptEvent::LongPress)
+                    }
+                    KeyCode::Char('a') => events.push_back(InterruptEvent::Alarm),
+                    _ => (),
+                }
+            }
+        }
+
+        if Instant::now() >= next_tick {
+            next_tick += tick;
+
+            // same order as the TIM5 interrupt in the firmware
+            events.push_back(InterruptEvent::Tick);
+            let long_press = app.state().settings.long_press_ticks();
+            if let Some(b) = button.update(button_pressed, long_press) {
+                events.push_back(match b {
+                    Button::ShortPress => InterruptEvent::ShortPress,
+                    Button::LongPress => InterruptEvent::LongPress,
+                });
+            }
+
+            if rtc.update() {
+                events.push_back(InterruptEvent::Alarm);
+            }
+        }
+
+        while let Some(evt) = events.pop_front() {
+            app.handle_event(evt, &mut rtc);
+            ring = app.take_ring_event().or(ring);
+        }
+
+        let cursor = app.render(&mut screen).unwrap();
+        draw(out, &screen, &cursor, ring, app.backlight())?;
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut out = io::stdout();
+
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen)?;
+
+    let key_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if key_releases {
+        execute!(
+            out,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )?;
+    }
+
+    let result = run(&mut out, key_releases);
+
+    if key_releases {
+        execute!(out, PopKeyboardEnhancementFlags)?;
+    }
+    execute!(
+        out,
+        SetCursorStyle::DefaultUserShape,
+        cursor::Show,
+        terminal::LeaveAlternateScreen
+    )?;
+    terminal::disable_raw_mode()?;
+
+    result
+}
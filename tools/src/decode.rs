@@ -0,0 +1,591 @@
+//! Reading the `LogEntry` stream back from a raw SD-card image.
+use std::{fmt, io};
+
+use dsaclk_core::{
+    alarm::{RingEvent, WakeReason},
+    logger::{
+        self, BlockHeader, LegacyLog, LogContents, LogEntry, LogHeader, BLOCK_HEADER_SIZE,
+        BLOCK_PAYLOAD_SIZE, FIRST_COMPATIBLE_SCHEMA, LOG_ENTRY_SCHEMA, LOG_FORMAT_VERSION,
+    },
+    storage::{
+        self, BlockDevice, FileBlockDevice, LOGGER_BLOCK_START_IDX, LOGGER_DATA_START_IDX,
+        SD_BLOCK_SIZE,
+    },
+    time::Timestamp,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    StorageError(storage::Error<io::Error>),
+    /// An entry could not be decoded at the given byte offset into the log.
+    CorruptEntry {
+        offset: usize,
+        error: postcard::Error,
+    },
+    /// The log was written in a format this version does not know how to read.
+    UnsupportedVersion {
+        version: u16,
+        schema: u16,
+    },
+    /// The block does not carry the `BlockHeader` expected at its position in the log.
+    UnexpectedBlock {
+        addr: u32,
+    },
+    /// The log was written before the `LogHeader` was introduced, and where it ends is no longer
+    /// stored on the card.
+    UnknownLegacyEnd,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::StorageError(storage::Error::DeviceError(e)) => write!(f, "I/O error: {}", e),
+            Error::StorageError(storage::Error::PostcardError(e)) => {
+                write!(f, "could not decode settings: {}", e)
+            }
+            Error::StorageError(e) => write!(f, "storage error: {:?}", e),
+            Error::CorruptEntry { offset, error } => write!(
+                f,
+                "could not decode log entry at byte {} of the log: {}",
+                offset, error
+            ),
+            Error::UnsupportedVersion { version, schema } => write!(
+                f,
+                "unsupported log format version {} with entry schema {}",
+                version, schema
+            ),
+            Error::UnexpectedBlock { addr } => {
+                write!(f, "block {} does not belong to the log", addr)
+            }
+            Error::UnknownLegacyEnd => write!(f, "the end of the log without header is unknown"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<storage::Error<io::Error>> for Error {
+    fn from(e: storage::Error<io::Error>) -> Self {
+        Error::StorageError(e)
+    }
+}
+
+fn read_block(dev: &mut FileBlockDevice, addr: u32) -> Result<[u8; SD_BLOCK_SIZE], Error> {
+    let mut block = [0u8; SD_BLOCK_SIZE];
+    dev.read_block(addr, &mut block)
+        .map_err(storage::Error::DeviceError)?;
+    Ok(block)
+}
+
+/// Reads the raw blocks in `start..end` into one contiguous buffer.
+pub fn read_log(dev: &mut FileBlockDevice, start: u32, end: u32) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    for addr in start..end {
+        data.extend_from_slice(&read_block(dev, addr)?);
+    }
+    Ok(data)
+}
+
+/// Reads the payloads of the blocks in `start..end`, with `None` for the blocks that do not belong
+/// to the log described by `header`.
+fn read_payloads(
+    dev: &mut FileBlockDevice,
+    header: &LogHeader,
+    start: u32,
+    end: u32,
+) -> Result<Vec<Option<Vec<u8>>>, Error> {
+    let mut payloads = Vec::new();
+    for addr in start..end {
+        let block = read_block(dev, addr)?;
+        payloads.push(match BlockHeader::parse(&block) {
+            Some(h) if h.version == header.version && h.seq == header.seq_at(addr) => {
+                Some(block[BLOCK_HEADER_SIZE..].to_vec())
+            }
+            _ => None,
+        });
+    }
+    Ok(payloads)
+}
+
+/// The entries read back from a log, along with how much of it had to be skipped.
+#[derive(Debug, Default)]
+pub struct Log {
+    pub entries: Vec<LogEntry>,
+    /// Blocks that did not carry the expected `BlockHeader`.
+    pub damaged_blocks: Vec<u32>,
+    /// Frames that failed the CRC check, including the parts of frames cut by damaged blocks.
+    pub damaged_frames: usize,
+}
+
+impl Log {
+    pub fn is_damaged(&self) -> bool {
+        !self.damaged_blocks.is_empty() || self.damaged_frames > 0
+    }
+}
+
+/// Reads and decodes all log entries on the device, up to the `end` block or the end of the log.
+///
+/// Damaged blocks and entries are skipped in logs written since format version 2. Logs written
+/// before the `LogHeader` was introduced are read as a bare stream of entries starting at
+/// `LOGGER_BLOCK_START_IDX`, followed by the log continuing them, see `LegacyLog`.
+pub fn read_entries(dev: &mut FileBlockDevice, end: Option<u32>) -> Result<Log, Error> {
+    if let Some(header) = LogHeader::parse(&read_block(dev, LOGGER_BLOCK_START_IDX)?) {
+        return read_log_at(dev, &header, LOGGER_DATA_START_IDX, end);
+    }
+
+    let legacy = LegacyLog::find(dev)?;
+    let legacy_end = match (legacy, end) {
+        (Some(legacy), Some(end)) => legacy.end.min(end),
+        (Some(legacy), None) => legacy.end,
+        (None, Some(end)) => end,
+        (None, None) => return Err(Error::UnknownLegacyEnd),
+    };
+    let mut log = Log {
+        entries: decode_entries(
+            &read_log(dev, LOGGER_BLOCK_START_IDX, legacy_end)?,
+            SD_BLOCK_SIZE,
+        )?,
+        ..Log::default()
+    };
+
+    if let Some(legacy) = legacy {
+        if let Some(header) = LogHeader::parse(&read_block(dev, legacy.end)?) {
+            let continued = read_log_at(dev, &header, legacy.end + 1, end)?;
+            log.entries.extend(continued.entries);
+            log.damaged_blocks = continued.damaged_blocks;
+            log.damaged_frames = continued.damaged_frames;
+        }
+    }
+    Ok(log)
+}
+
+/// Reads the log described by `header` whose first block is `start`, like `read_entries`.
+fn read_log_at(
+    dev: &mut FileBlockDevice,
+    header: &LogHeader,
+    start: u32,
+    end: Option<u32>,
+) -> Result<Log, Error> {
+    if !(FIRST_COMPATIBLE_SCHEMA..=LOG_ENTRY_SCHEMA).contains(&header.schema)
+        || !(1..=LOG_FORMAT_VERSION).contains(&header.version)
+    {
+        return Err(Error::UnsupportedVersion {
+            version: header.version,
+            schema: header.schema,
+        });
+    }
+
+    let end = match end {
+        Some(end) => end,
+        None => logger::find_log_end(dev, header, start)?,
+    };
+
+    let mut log = Log::default();
+    let mut data = Vec::new();
+    for (addr, payload) in (start..).zip(read_payloads(dev, header, start, end)?) {
+        match payload {
+            Some(payload) => data.extend(payload),
+            // the version 1 stream cannot be resynchronized after a missing block
+            None if header.version == 1 => return Err(Error::UnexpectedBlock { addr }),
+            None => {
+                // ends the frame cut by the damaged block, so the next one is decoded again
+                data.push(0);
+                log.damaged_blocks.push(addr);
+            }
+        }
+    }
+
+    if header.version == 1 {
+        log.entries = decode_entries(&data, BLOCK_PAYLOAD_SIZE)?;
+    } else {
+        let (entries, damaged_frames) = decode_frames(&data);
+        log.entries = entries;
+        log.damaged_frames = damaged_frames;
+    }
+    Ok(log)
+}
+
+/// Decodes the zero-terminated frames written by the `Logger` since format version 2. Returns the
+/// entries and the number of damaged frames that were skipped.
+pub fn decode_frames(data: &[u8]) -> (Vec<LogEntry>, usize) {
+    let mut entries = Vec::new();
+    let mut damaged = 0;
+
+    // runs of zeroes, such as the padding after a flush, are empty frames
+    for frame in data.split(|&b| b == 0).filter(|f| !f.is_empty()) {
+        match logger::decode_frame(frame) {
+            Some(entry) => entries.push(entry),
+            None => damaged += 1,
+        }
+    }
+
+    (entries, damaged)
+}
+
+/// Decodes the stream of bare postcard-serialized `LogEntry` values written by the `Logger` into
+/// blocks (or block payloads) of `block_size` bytes, before format version 2.
+///
+/// When the logger is flushed, the rest of the last block is filled with zeroes and the next
+/// entry starts at the following block boundary. A run of zeroes reaching the end of a block is
+/// therefore treated as padding and skipped, unless it is short enough to be the start of an entry
+/// logged shortly after midnight (leading zero hour, minute or second) that continues into the
+/// next block.
+pub fn decode_entries(data: &[u8], block_size: usize) -> Result<Vec<LogEntry>, Error> {
+    let mut entries: Vec<LogEntry> = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let block_end = ((offset / block_size + 1) * block_size).min(data.len());
+        if data[offset..block_end].iter().all(|&b| b == 0)
+            && !continues_entry(&data[offset..], block_end - offset, entries.last())
+        {
+            offset = block_end;
+            continue;
+        }
+
+        let (entry, rest) = postcard::take_from_bytes::<LogEntry>(&data[offset..])
+            .map_err(|error| Error::CorruptEntry { offset, error })?;
+        offset = data.len() - rest.len();
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Checks whether the `zeroes` bytes at the start of `data` begin an entry rather than padding.
+///
+/// The day, month and year of a timestamp are never zero, so only its first three fields fit in
+/// the run. Such an entry must also directly follow the previous one in time, which the padding
+/// decoded together with the start of the next block is very unlikely to do.
+fn continues_entry(data: &[u8], zeroes: usize, previous: Option<&LogEntry>) -> bool {
+    const MAX_ZERO_PREFIX: usize = 3;
+
+    if zeroes > MAX_ZERO_PREFIX {
+        return false;
+    }
+
+    match (postcard::from_bytes::<LogEntry>(data), previous) {
+        (Ok(entry), Some(previous)) => {
+            let elapsed = Timestamp::from(&entry.timestamp) - Timestamp::from(&previous.timestamp);
+            (0..=24 * 60 * 60).contains(&elapsed.seconds())
+        }
+        _ => false,
+    }
+}
+
+/// Header line matching the rows produced by `csv_row`.
+pub const CSV_HEADER: &str = "timestamp,kind,acc_x,acc_y,acc_z,temp_mean,gyro_mag_max,alarm";
+
+/// Formats an entry as one CSV row, leaving the measurement columns empty for other contents and
+/// the alarm column empty for entries not about one of the `Alarm`s.
+pub fn csv_row(entry: &LogEntry) -> String {
+    let timestamp = Timestamp::from(&entry.timestamp).to_string();
+
+    match &entry.contents {
+        LogContents::Measurement(m) => format!(
+            "{},measurement,{},{},{},{},{},",
+            timestamp, m.acc_mean.0, m.acc_mean.1, m.acc_mean.2, m.temp_mean, m.gyro_mag_max
+        ),
+        LogContents::Alarm() => format!("{},alarm,,,,,,", timestamp),
+        LogContents::Ring(e) => {
+            let (kind, alarm) = match *e {
+                RingEvent::Ring(alarm) => ("ring", alarm),
+                RingEvent::Snooze => ("snooze", None),
+                RingEvent::Dismiss => ("dismiss", None),
+                RingEvent::Timeout => ("timeout", None),
+                RingEvent::Wake(alarm, WakeReason::LightSleep) => ("wake_light_sleep", Some(alarm)),
+                RingEvent::Wake(alarm, WakeReason::SetTime) => ("wake_set_time", Some(alarm)),
+            };
+            let alarm = alarm.map(|i| i.to_string()).unwrap_or_default();
+            format!("{},{},,,,,,{}", timestamp, kind, alarm)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dsaclk_core::{
+        clock::ClockState,
+        logger::{LogBuffer, LogTimestamp, Logger},
+        mpu::Measurement,
+        storage::SETTINGS_BLOCK_IDX,
+    };
+    use std::fs::{self, OpenOptions};
+
+    /// Runs `f` with a block device backed by a fresh temporary image.
+    fn with_image(name: &str, f: impl FnOnce(&mut FileBlockDevice)) {
+        let path =
+            std::env::temp_dir().join(format!("dsaclk-decode-{}-{}.img", name, std::process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(64 * SD_BLOCK_SIZE as u64).unwrap();
+
+        f(&mut FileBlockDevice::new(file).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    fn entry(hour: u8, minute: u8, second: u8) -> LogEntry {
+        LogEntry {
+            timestamp: LogTimestamp {
+                hour,
+                minute,
+                second,
+                day: 21,
+                month: 9,
+                year: 21,
+            },
+            contents: LogContents::Measurement(Measurement::default()),
+        }
+    }
+
+    #[test]
+    fn skips_padding_after_flush() {
+        let mut data = postcard::to_stdvec(&entry(23, 59, 58)).unwrap();
+        data.resize(SD_BLOCK_SIZE, 0);
+        data.extend(postcard::to_stdvec(&entry(23, 59, 59)).unwrap());
+
+        let entries = decode_entries(&data, SD_BLOCK_SIZE).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].timestamp.second, 59);
+    }
+
+    #[test]
+    fn keeps_entry_starting_with_zeroes_at_block_end() {
+        let alarm = LogEntry {
+            contents: LogContents::Alarm(),
+            ..entry(23, 59, 59)
+        };
+        let mut data = postcard::to_stdvec(&entry(23, 59, 59)).unwrap();
+        while data.len() < SD_BLOCK_SIZE - 2 {
+            data.extend(postcard::to_stdvec(&alarm).unwrap());
+        }
+        assert_eq!(data.len(), SD_BLOCK_SIZE - 2);
+
+        // the zero hour and minute of the next entry are the last bytes of the block
+        let mut next = entry(0, 0, 1);
+        next.timestamp.day += 1;
+        data.extend(postcard::to_stdvec(&next).unwrap());
+
+        let entries = decode_entries(&data, SD_BLOCK_SIZE).unwrap();
+        assert_eq!(entries.len(), 71);
+        assert_eq!(entries[70].timestamp.day, 22);
+    }
+
+    #[test]
+    fn reads_versioned_log() {
+        with_image("versioned", |dev| {
+            let mut logger = Logger::open(dev).unwrap();
+            for second in 0..60 {
+                let clock = ClockState {
+                    second,
+                    ..ClockState::default()
+                };
+                logger.append(&clock, entry(0, 0, 0).contents, dev).unwrap();
+            }
+            logger.flush(dev).unwrap();
+
+            let log = read_entries(dev, None).unwrap();
+            assert_eq!(log.entries.len(), 60);
+            assert_eq!(log.entries[59].timestamp.second, 59);
+            assert!(!log.is_damaged());
+        });
+    }
+
+    #[test]
+    fn reads_legacy_log() {
+        with_image("legacy", |dev| {
+            let mut block = [0u8; SD_BLOCK_SIZE];
+            postcard::to_slice(&entry(22, 0, 0), &mut block).unwrap();
+            dev.write_block(LOGGER_BLOCK_START_IDX, &block).unwrap();
+
+            // the settings only held the next block to write at the time
+            let mut settings = [0u8; SD_BLOCK_SIZE];
+            postcard::to_slice(&(LOGGER_BLOCK_START_IDX + 1), &mut settings).unwrap();
+            dev.write_block(SETTINGS_BLOCK_IDX, &settings).unwrap();
+
+            let entries = read_entries(dev, None).unwrap().entries;
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].timestamp.hour, 22);
+
+            // followed by the log continuing it
+            let mut logger = Logger::open(dev).unwrap();
+            logger
+                .append(&ClockState::default(), entry(23, 0, 0).contents, dev)
+                .unwrap();
+            logger.flush(dev).unwrap();
+            dev.write_block(SETTINGS_BLOCK_IDX, &[0u8; SD_BLOCK_SIZE])
+                .unwrap();
+
+            let log = read_entries(dev, None).unwrap();
+            assert_eq!(log.entries.len(), 2);
+            assert_eq!(log.entries[0].timestamp.hour, 22);
+            assert!(!log.is_damaged());
+        });
+    }
+
+    #[test]
+    fn formats_csv_rows() {
+        let mut e = entry(6, 30, 0);
+        assert!(csv_row(&e).starts_with("2021-09-21T06:30:00,measurement,"));
+        assert!(csv_row(&e).ends_with(','));
+        e.contents = LogContents::Ring(RingEvent::Ring(Some(1)));
+        assert_eq!(csv_row(&e), "2021-09-21T06:30:00,ring,,,,,,1");
+        e.contents = LogContents::Ring(RingEvent::Ring(None));
+        assert_eq!(csv_row(&e), "2021-09-21T06:30:00,ring,,,,,,");
+        e.contents = LogContents::Ring(RingEvent::Snooze);
+        assert_eq!(csv_row(&e), "2021-09-21T06:30:00,snooze,,,,,,");
+        e.contents = LogContents::Ring(RingEvent::Wake(0, WakeReason::LightSleep));
+        assert_eq!(csv_row(&e), "2021-09-21T06:30:00,wake_light_sleep,,,,,,0");
+    }
+
+    #[test]
+    fn refuses_unknown_version() {
+        with_image("unknown", |dev| {
+            let mut block = [0u8; SD_BLOCK_SIZE];
+            let header = LogHeader {
+                version: LOG_FORMAT_VERSION + 1,
+                ..LogHeader::new(0)
+            };
+            postcard::to_slice(&header, &mut block).unwrap();
+            dev.write_block(LOGGER_BLOCK_START_IDX, &block).unwrap();
+
+            assert!(matches!(
+                read_entries(dev, Some(LOGGER_DATA_START_IDX + 1)),
+                Err(Error::UnsupportedVersion { .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn skips_blocks_from_another_log() {
+        with_image("stale", |dev| {
+            let mut logger = Logger::open(dev).unwrap();
+            logger
+                .append(&ClockState::default(), LogContents::Alarm(), dev)
+                .unwrap();
+            logger.flush(dev).unwrap();
+
+            // a new log leaves the old block behind
+            Logger::create(dev).unwrap();
+
+            let log = read_entries(dev, Some(LOGGER_DATA_START_IDX + 1)).unwrap();
+            assert!(log.entries.is_empty());
+            assert_eq!(log.damaged_blocks, vec![LOGGER_DATA_START_IDX]);
+        });
+    }
+
+    /// The entry logged `i` seconds after midnight.
+    fn numbered(i: usize) -> LogEntry {
+        entry((i / 3600) as u8, (i / 60 % 60) as u8, (i % 60) as u8)
+    }
+
+    fn number(entry: &LogEntry) -> usize {
+        let t = &entry.timestamp;
+        t.hour as usize * 3600 + t.minute as usize * 60 + t.second as usize
+    }
+
+    /// Checks that the entries are a subset of `0..count` in order, missing at most `lost`.
+    fn assert_recovered(entries: &[LogEntry], count: usize, lost: usize) {
+        let numbers: Vec<_> = entries.iter().map(number).collect();
+        assert!(numbers.windows(2).all(|w| w[0] < w[1]), "{:?}", numbers);
+        assert!(numbers.iter().all(|&n| n < count));
+        assert!(
+            numbers.len() + lost >= count,
+            "recovered {} of {}",
+            numbers.len(),
+            count
+        );
+    }
+
+    fn frame_len() -> usize {
+        logger::encode_frame(&numbered(0), &mut [0u8; SD_BLOCK_SIZE]).unwrap()
+    }
+
+    /// Logs `count` numbered entries, returning the end of the log.
+    fn write_numbered(dev: &mut FileBlockDevice, count: usize) -> u32 {
+        let mut logger = Logger::open(dev).unwrap();
+        for i in 0..count {
+            let e = numbered(i);
+            let clock = ClockState {
+                hour: e.timestamp.hour,
+                minute: e.timestamp.minute,
+                second: e.timestamp.second,
+                ..ClockState::default()
+            };
+            logger.append(&clock, e.contents, dev).unwrap();
+        }
+        logger.flush(dev).unwrap();
+        logger.next_block()
+    }
+
+    #[test]
+    fn recovers_from_any_flipped_bit() {
+        let count = 60;
+        let mut buffer = LogBuffer::new();
+        let mut data = Vec::new();
+        for i in 0..count {
+            buffer.push(&numbered(i)).unwrap();
+            while buffer.has_full_block() {
+                data.extend(buffer.take_block().unwrap());
+            }
+        }
+        data.extend(buffer.take_block().unwrap());
+
+        // a flipped bit damages one frame, or two if it hits or creates a delimiter
+        for bit in BLOCK_PAYLOAD_SIZE * 8..BLOCK_PAYLOAD_SIZE * 16 {
+            let mut damaged = data.clone();
+            damaged[bit / 8] ^= 1 << (bit % 8);
+
+            let (entries, damaged_frames) = decode_frames(&damaged);
+            assert_recovered(&entries, count, 2);
+            if entries.len() < count {
+                assert!(damaged_frames > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn recovers_after_overwritten_block() {
+        with_image("garbage", |dev| {
+            let count = 500;
+            let end = write_numbered(dev, count);
+
+            let frames_per_block = BLOCK_PAYLOAD_SIZE / frame_len() + 1;
+            let addr = LOGGER_DATA_START_IDX + 3;
+            let mut block = [0u8; SD_BLOCK_SIZE];
+            dev.read_block(addr, &mut block).unwrap();
+            block[BLOCK_HEADER_SIZE..].fill(0xa5);
+            dev.write_block(addr, &block).unwrap();
+
+            let log = read_entries(dev, Some(end)).unwrap();
+            assert_recovered(&log.entries, count, frames_per_block + 2);
+            assert!(log.damaged_blocks.is_empty());
+            assert!(log.damaged_frames > 0);
+        });
+    }
+
+    #[test]
+    fn skips_block_with_damaged_header() {
+        with_image("header", |dev| {
+            let count = 500;
+            let end = write_numbered(dev, count);
+
+            let frames_per_block = BLOCK_PAYLOAD_SIZE / frame_len() + 1;
+            let addr = LOGGER_DATA_START_IDX + 5;
+            let mut block = [0u8; SD_BLOCK_SIZE];
+            dev.read_block(addr, &mut block).unwrap();
+            block[BLOCK_HEADER_SIZE - 1] ^= 0x10;
+            dev.write_block(addr, &block).unwrap();
+
+            let log = read_entries(dev, Some(end)).unwrap();
+            assert_recovered(&log.entries, count, frames_per_block + 2);
+            assert_eq!(log.damaged_blocks, vec![addr]);
+        });
+    }
+}
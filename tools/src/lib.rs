@@ -0,0 +1,5 @@
+//! Host-side tools for working with data recorded by the `dsaclk` firmware.
+
+pub mod actigraphy;
+pub mod decode;
+pub mod report;
@@ -0,0 +1,286 @@
+//! Per-night sleep metrics computed from the scored epochs.
+use std::fmt;
+
+use dsaclk_core::time::Timestamp;
+use serde::Serialize;
+
+use crate::actigraphy::{ScoredEpoch, SleepWake};
+
+/// How the recording is split into nights and when sleep is considered to have started.
+#[derive(Debug, Clone, Copy)]
+pub struct NightConfig {
+    /// Gaps in the log of at least this many seconds end a night.
+    pub split_gap: i64,
+    /// Continuous wake of at least this many seconds ends a night (e.g. out of bed for the day).
+    pub split_wake: i64,
+    /// Sleep onset is the start of the first sleep period lasting at least this many seconds.
+    pub onset_duration: i64,
+    /// Periods with less total sleep than this many seconds are not reported as nights.
+    pub min_sleep: i64,
+}
+
+impl Default for NightConfig {
+    fn default() -> Self {
+        NightConfig {
+            split_gap: 60 * 60,
+            split_wake: 2 * 60 * 60,
+            onset_duration: 10 * 60,
+            min_sleep: 60 * 60,
+        }
+    }
+}
+
+/// Sleep metrics of one night. All durations are in minutes.
+#[derive(Debug, Clone, Serialize)]
+pub struct NightReport {
+    pub start: String,
+    pub end: String,
+    pub time_in_bed: f32,
+    pub total_sleep_time: f32,
+    pub sleep_onset_latency: f32,
+    pub wake_after_sleep_onset: f32,
+    pub awakenings: u32,
+    /// Total sleep time as a percentage of the time in bed.
+    pub sleep_efficiency: f32,
+    /// Mean of `temp_mean` over the night in °C, if any measurements were made.
+    pub mean_temperature: Option<f32>,
+}
+
+fn is_gap(e: &ScoredEpoch) -> bool {
+    e.epoch.samples == 0
+}
+
+fn is_sleep(e: &ScoredEpoch) -> bool {
+    !is_gap(e) && e.state == SleepWake::Sleep
+}
+
+/// Splits the epochs into nights at long gaps and long periods of wake, which are left out.
+pub fn split_nights<'a>(epochs: &'a [ScoredEpoch], config: &NightConfig) -> Vec<&'a [ScoredEpoch]> {
+    let epoch_len = match epochs {
+        [a, b, ..] => b.epoch.start - a.epoch.start,
+        _ => return Vec::new(),
+    };
+
+    let mut nights = Vec::new();
+    let mut night_start = 0;
+    let mut i = 0;
+
+    while i < epochs.len() {
+        // find the run of epochs of the same kind starting here
+        let gap = is_gap(&epochs[i]);
+        let wake = !gap && !is_sleep(&epochs[i]);
+        let run_end = epochs[i..]
+            .iter()
+            .position(|e| is_gap(e) != gap || (!gap && is_sleep(e) == wake))
+            .map_or(epochs.len(), |p| i + p);
+
+        let duration = (run_end - i) as i64 * epoch_len;
+        if (gap && duration >= config.split_gap) || (wake && duration >= config.split_wake) {
+            if i > night_start {
+                nights.push(&epochs[night_start..i]);
+            }
+            night_start = run_end;
+        }
+        i = run_end;
+    }
+    if night_start < epochs.len() {
+        nights.push(&epochs[night_start..]);
+    }
+
+    nights
+        .into_iter()
+        .filter(|n| n.iter().filter(|e| is_sleep(e)).count() as i64 * epoch_len >= config.min_sleep)
+        .collect()
+}
+
+/// Computes the metrics of one night, trimming any gaps at its start and end.
+pub fn night_report(epochs: &[ScoredEpoch], epoch_len: i64, config: &NightConfig) -> NightReport {
+    let first = epochs.iter().position(|e| !is_gap(e)).unwrap_or(0);
+    let last = epochs.iter().rposition(|e| !is_gap(e)).unwrap_or(0);
+    let epochs = &epochs[first..=last];
+
+    let minutes = |epochs: usize| (epochs as i64 * epoch_len) as f32 / 60.0;
+
+    // the first sleep period lasting long enough marks the sleep onset
+    let onset_epochs = (config.onset_duration / epoch_len).max(1) as usize;
+    let onset = epochs
+        .windows(onset_epochs.min(epochs.len()))
+        .position(|w| w.iter().all(is_sleep))
+        .unwrap_or(epochs.len());
+    let final_wake = epochs.iter().rposition(is_sleep).map_or(onset, |p| p + 1);
+
+    let sleep_period = &epochs[onset..final_wake.max(onset)];
+    let total_sleep = epochs.iter().filter(|e| is_sleep(e)).count();
+    let waso = sleep_period
+        .iter()
+        .filter(|e| !is_gap(e) && !is_sleep(e))
+        .count();
+    let awakenings = sleep_period
+        .windows(2)
+        .filter(|w| is_sleep(&w[0]) && !is_gap(&w[1]) && !is_sleep(&w[1]))
+        .count() as u32;
+
+    let (temp_sum, samples) = epochs.iter().fold((0.0, 0), |(sum, n), e| {
+        (
+            sum + e.epoch.temp_mean.unwrap_or(0.0) * e.epoch.samples as f32,
+            n + e.epoch.samples,
+        )
+    });
+
+    let time_in_bed = minutes(epochs.len());
+    let total_sleep_time = minutes(total_sleep);
+
+    NightReport {
+        start: Timestamp::from_seconds(epochs[0].epoch.start).to_string(),
+        end: Timestamp::from_seconds(epochs[epochs.len() - 1].epoch.start + epoch_len).to_string(),
+        time_in_bed,
+        total_sleep_time,
+        sleep_onset_latency: minutes(onset),
+        wake_after_sleep_onset: minutes(waso),
+        awakenings,
+        sleep_efficiency: if time_in_bed > 0.0 {
+            100.0 * total_sleep_time / time_in_bed
+        } else {
+            0.0
+        },
+        mean_temperature: if samples > 0 {
+            Some(temp_sum / samples as f32)
+        } else {
+            None
+        },
+    }
+}
+
+/// Splits the scored epochs into nights and computes the metrics of each.
+pub fn nightly_reports(epochs: &[ScoredEpoch], config: &NightConfig) -> Vec<NightReport> {
+    let epoch_len = match epochs {
+        [a, b, ..] => b.epoch.start - a.epoch.start,
+        _ => return Vec::new(),
+    };
+
+    split_nights(epochs, config)
+        .into_iter()
+        .map(|night| night_report(night, epoch_len, config))
+        .collect()
+}
+
+fn hours_minutes(minutes: f32) -> String {
+    let minutes = minutes.round() as u32;
+    match minutes / 60 {
+        0 => format!("{}m", minutes),
+        h => format!("{}h {:02}m", h, minutes % 60),
+    }
+}
+
+impl fmt::Display for NightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Night {} - {}", self.start, self.end)?;
+        writeln!(
+            f,
+            "  time in bed             {:>8}",
+            hours_minutes(self.time_in_bed)
+        )?;
+        writeln!(
+            f,
+            "  total sleep time        {:>8}",
+            hours_minutes(self.total_sleep_time)
+        )?;
+        writeln!(
+            f,
+            "  sleep onset latency     {:>8}",
+            hours_minutes(self.sleep_onset_latency)
+        )?;
+        writeln!(
+            f,
+            "  wake after sleep onset  {:>8}",
+            hours_minutes(self.wake_after_sleep_onset)
+        )?;
+        writeln!(f, "  awakenings              {:>8}", self.awakenings)?;
+        writeln!(
+            f,
+            "  sleep efficiency        {:>6.1} %",
+            self.sleep_efficiency
+        )?;
+        match self.mean_temperature {
+            Some(t) => writeln!(f, "  mean temperature        {:>5.1} °C", t),
+            None => writeln!(f, "  mean temperature        {:>8}", "-"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actigraphy::Epoch;
+
+    /// One minute epochs: 's' sleep, 'w' wake and '.' gap.
+    fn night(pattern: &str) -> Vec<ScoredEpoch> {
+        pattern
+            .chars()
+            .enumerate()
+            .map(|(i, c)| ScoredEpoch {
+                epoch: Epoch {
+                    start: i as i64 * 60,
+                    activity: 0.0,
+                    samples: if c == '.' { 0 } else { 60 },
+                    temp_mean: if c == '.' { None } else { Some(20.0) },
+                },
+                state: if c == 's' {
+                    SleepWake::Sleep
+                } else {
+                    SleepWake::Wake
+                },
+            })
+            .collect()
+    }
+
+    fn config() -> NightConfig {
+        NightConfig {
+            split_gap: 5 * 60,
+            split_wake: 10 * 60,
+            onset_duration: 3 * 60,
+            min_sleep: 5 * 60,
+        }
+    }
+
+    #[test]
+    fn metrics_of_one_night() {
+        // 2 minutes until a short nap, then onset after 6 minutes
+        let epochs = night("wwswwwsssssswwssssw");
+        let reports = nightly_reports(&epochs, &config());
+        assert_eq!(reports.len(), 1);
+
+        let r = &reports[0];
+        assert_eq!(r.time_in_bed, 19.0);
+        assert_eq!(r.total_sleep_time, 11.0);
+        assert_eq!(r.sleep_onset_latency, 6.0);
+        assert_eq!(r.wake_after_sleep_onset, 2.0);
+        assert_eq!(r.awakenings, 1);
+        assert!((r.sleep_efficiency - 100.0 * 11.0 / 19.0).abs() < 1e-3);
+        assert_eq!(r.mean_temperature, Some(20.0));
+        assert_eq!(r.start, "2000-01-01T00:00:00");
+        assert_eq!(r.end, "2000-01-01T00:19:00");
+    }
+
+    #[test]
+    fn nights_split_on_gaps_and_long_wake() {
+        let epochs = night("ssssss......sssssswwwwwwwwwwwwsssssss..ssw");
+        let nights = split_nights(&epochs, &config());
+
+        assert_eq!(nights.len(), 3);
+        assert_eq!(nights[0].len(), 6);
+        assert_eq!(nights[1].len(), 6);
+        // the short gap is kept within the night, but not counted as sleep
+        assert_eq!(nights[2].len(), 12);
+
+        let r = night_report(nights[2], 60, &config());
+        assert_eq!(r.total_sleep_time, 9.0);
+        assert_eq!(r.wake_after_sleep_onset, 0.0);
+    }
+
+    #[test]
+    fn short_sleep_is_not_a_night() {
+        let epochs = night("wwwsssswwwwwwwwwwwwww");
+        assert!(split_nights(&epochs, &config()).is_empty());
+    }
+}